@@ -0,0 +1,41 @@
+use common::create_etl_job;
+use database::*;
+
+#[derive(Debug, Default, Clone)]
+struct State {}
+
+fn handle_data(
+    table: Table,
+    range: RangeQuery,
+    _source: &mut PgConnection,
+    _sink: &mut PgConnection,
+    _state: &mut State,
+) -> eyre::Result<Option<(Table, RangeQuery)>> {
+    log::info!("Processing changes for table: {:?}", table);
+    log::info!("Range: {:?}", range);
+
+    // TODO: query the changed rows of `table` from `source` with `RowStream::query`,
+    // write the result to `sink` and return the changed range of the produced table
+    Ok(None)
+}
+
+create_etl_job!(
+    id => "__template__",
+    consumes => [__consumes__],
+    produces => [__produces__],
+    state => State,
+    handle_data
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_declaration() {
+        let registration = registration();
+        assert_eq!(registration.id, "__template__");
+        assert_eq!(registration.consumes, vec![__consumes__]);
+        assert_eq!(registration.produces, vec![__produces__]);
+    }
+}
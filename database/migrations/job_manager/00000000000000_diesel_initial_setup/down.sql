@@ -4,4 +4,3 @@
 
 DROP FUNCTION IF EXISTS diesel_manage_updated_at(_tbl regclass);
 DROP FUNCTION IF EXISTS diesel_set_updated_at();
-DROP TABLE IF EXISTS __etl_job_status;
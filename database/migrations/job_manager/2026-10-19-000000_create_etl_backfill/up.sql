@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS __etl_backfill
+(
+    id               BIGSERIAL PRIMARY KEY,
+    job_id           VARCHAR(255) NOT NULL,
+    request          JSONB        NOT NULL,
+    chunk_size       BIGINT       NOT NULL CHECK (chunk_size > 0),
+    total_chunks     BIGINT       NOT NULL CHECK (total_chunks >= 0),
+    processed_chunks BIGINT       NOT NULL DEFAULT 0,
+    throttle_ms      BIGINT       NOT NULL DEFAULT 0,
+    status           VARCHAR(32)  NOT NULL,
+    created_at       TIMESTAMP    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at       TIMESTAMP    NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_etl_backfill_job_id_status ON __etl_backfill (job_id, status);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS __etl_watermark
+(
+    job_id        VARCHAR(255) NOT NULL,
+    source_table  VARCHAR(64)  NOT NULL,
+    partition_key VARCHAR(64)  NOT NULL,
+    watermark     JSONB        NOT NULL,
+    gaps          JSONB        NOT NULL DEFAULT '[]',
+    updated_at    TIMESTAMP    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (job_id, source_table, partition_key)
+);
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS __etl_outbox
+(
+    id         BIGSERIAL PRIMARY KEY,
+    job_id     VARCHAR(255) NOT NULL,
+    message    JSONB        NOT NULL,
+    created_at TIMESTAMP    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    sent_at    TIMESTAMP
+);
+
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_etl_outbox_unsent ON __etl_outbox (job_id, id) WHERE sent_at IS NULL;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE __etl_watermark
+    DROP COLUMN reorg_count,
+    DROP COLUMN last_reorg_depth,
+    DROP COLUMN max_reorg_depth;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- Reorgs of the partition: processed values replaced upstream, eg: the blocks of a chain
+ALTER TABLE __etl_watermark
+    ADD COLUMN IF NOT EXISTS reorg_count      BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS last_reorg_depth BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS max_reorg_depth  BIGINT NOT NULL DEFAULT 0;
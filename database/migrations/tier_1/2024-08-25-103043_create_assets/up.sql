@@ -13,10 +13,12 @@ CREATE TABLE IF NOT EXISTS assets
 
 -- Index asset unique address + chain_id
 CREATE UNIQUE INDEX IF NOT EXISTS idx_assets_address_chain_id ON assets (address, chain_id);
-CREATE INDEX idx_assets_symbol ON assets (symbol);
+CREATE INDEX IF NOT EXISTS idx_assets_symbol ON assets (symbol);
 
 -- Insert default assets
 INSERT INTO assets (address, name, decimals, symbol, chain_id)
-values ('0xdac17f958d2ee523a2206206994597c13d831ec7', 'USDT', 6, 'USDT', 1);
+values ('0xdac17f958d2ee523a2206206994597c13d831ec7', 'USDT', 6, 'USDT', 1)
+ON CONFLICT (address, chain_id) DO NOTHING;
 INSERT INTO assets (address, name, decimals, symbol, chain_id)
-values ('0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2', 'ETH', 18, 'ETH', 1);
+values ('0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2', 'ETH', 18, 'ETH', 1)
+ON CONFLICT (address, chain_id) DO NOTHING;
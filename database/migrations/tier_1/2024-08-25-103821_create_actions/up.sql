@@ -28,7 +28,7 @@ CREATE TABLE IF NOT EXISTS actions
 );
 
 -- Indexes
-CREATE INDEX idx_actions_tx_hash ON actions (tx_hash);
-CREATE INDEX idx_actions_wallet_address ON actions (wallet_address);
-CREATE INDEX idx_actions_block_timestamp ON actions (block_timestamp);
-CREATE INDEX idx_actions_chain_id_block_number ON actions (chain_id, block_number asc);
+CREATE INDEX IF NOT EXISTS idx_actions_tx_hash ON actions (tx_hash);
+CREATE INDEX IF NOT EXISTS idx_actions_wallet_address ON actions (wallet_address);
+CREATE INDEX IF NOT EXISTS idx_actions_block_timestamp ON actions (block_timestamp);
+CREATE INDEX IF NOT EXISTS idx_actions_chain_id_block_number ON actions (chain_id, block_number asc);
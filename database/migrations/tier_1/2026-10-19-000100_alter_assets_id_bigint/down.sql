@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE assets ALTER COLUMN id TYPE INTEGER;
+ALTER SEQUENCE assets_id_seq AS INTEGER;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- assets.id is referenced by the BIGINT actions.asset_id and mapped as BigSerial
+ALTER SEQUENCE assets_id_seq AS BIGINT;
+ALTER TABLE assets ALTER COLUMN id TYPE BIGINT;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS buy_sell
+(
+    "user"    VARCHAR(80) NOT NULL,
+    amount    BIGINT      NOT NULL,
+    timestamp TIMESTAMP   NOT NULL,
+    PRIMARY KEY ("user", timestamp)
+);
+
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_buy_sell_timestamp ON buy_sell (timestamp);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE buy_sell ALTER COLUMN amount TYPE BIGINT USING trunc(amount)::BIGINT;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Amounts are exact token amounts, the scale of each value holds its decimals
+ALTER TABLE buy_sell ALTER COLUMN amount TYPE NUMERIC USING amount::NUMERIC;
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS idx_buy_sell_chain_id_timestamp;
+-- Rows of several chains & assets would collide on the old key
+DELETE FROM buy_sell;
+ALTER TABLE buy_sell DROP CONSTRAINT buy_sell_pkey;
+ALTER TABLE buy_sell DROP COLUMN chain_id, DROP COLUMN asset_id;
+ALTER TABLE buy_sell ADD PRIMARY KEY ("user", timestamp);
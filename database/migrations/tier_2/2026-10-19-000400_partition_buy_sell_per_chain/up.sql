@@ -0,0 +1,13 @@
+-- Your SQL goes here
+-- Rows are partitioned per chain & asset. Rows written before have neither, they are deleted:
+-- backfill the actions of every chain to rewrite them with their real chain & asset
+DELETE FROM buy_sell;
+ALTER TABLE buy_sell
+    ADD COLUMN IF NOT EXISTS chain_id BIGINT NOT NULL,
+    ADD COLUMN IF NOT EXISTS asset_id BIGINT NOT NULL;
+ALTER TABLE buy_sell
+    DROP CONSTRAINT buy_sell_pkey,
+    ADD PRIMARY KEY ("user", chain_id, asset_id, timestamp);
+
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_buy_sell_chain_id_timestamp ON buy_sell (chain_id, timestamp);
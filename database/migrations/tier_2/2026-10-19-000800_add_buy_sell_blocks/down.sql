@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS idx_buy_sell_chain_id_to_block;
+ALTER TABLE buy_sell DROP COLUMN from_block, DROP COLUMN to_block;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- Blocks of the actions summed in a row, so a reorg rolls the rows of a chain back from the
+-- replaced blocks. Rows written before have none, they are deleted: backfill the actions of
+-- every chain to rewrite them
+DELETE FROM buy_sell;
+ALTER TABLE buy_sell
+    ADD COLUMN IF NOT EXISTS from_block BIGINT NOT NULL,
+    ADD COLUMN IF NOT EXISTS to_block BIGINT NOT NULL;
+
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_buy_sell_chain_id_to_block ON buy_sell (chain_id, to_block);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS balance_per_date
+(
+    "user"  VARCHAR(80) NOT NULL,
+    balance BIGINT      NOT NULL,
+    date    DATE        NOT NULL,
+    PRIMARY KEY ("user", date)
+);
+
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_balance_per_date_date ON balance_per_date (date);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE balance_per_date ALTER COLUMN balance TYPE BIGINT USING trunc(balance)::BIGINT;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Balances are exact token amounts, the scale of each value holds its decimals
+ALTER TABLE balance_per_date ALTER COLUMN balance TYPE NUMERIC USING balance::NUMERIC;
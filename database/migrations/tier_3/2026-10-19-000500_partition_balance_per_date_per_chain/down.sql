@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS idx_balance_per_date_chain_id_date;
+-- Rows of several chains & assets would collide on the old key
+DELETE FROM balance_per_date;
+ALTER TABLE balance_per_date DROP CONSTRAINT balance_per_date_pkey;
+ALTER TABLE balance_per_date DROP COLUMN chain_id, DROP COLUMN asset_id;
+ALTER TABLE balance_per_date ADD PRIMARY KEY ("user", date);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+-- Balances are partitioned per chain & asset. Balances written before have neither, they are
+-- deleted: they are recomputed when the buy-sell rows are backfilled
+DELETE FROM balance_per_date;
+ALTER TABLE balance_per_date
+    ADD COLUMN IF NOT EXISTS chain_id BIGINT NOT NULL,
+    ADD COLUMN IF NOT EXISTS asset_id BIGINT NOT NULL;
+ALTER TABLE balance_per_date
+    DROP CONSTRAINT balance_per_date_pkey,
+    ADD PRIMARY KEY ("user", chain_id, asset_id, date);
+
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_balance_per_date_chain_id_date ON balance_per_date (chain_id, date);
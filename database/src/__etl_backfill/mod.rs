@@ -0,0 +1,154 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use strum::Display;
+use strum::EnumString;
+mod schemas;
+
+#[derive(EnumString, Display, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum BackfillStatus {
+    Running,
+    Paused,
+    Finished,
+}
+
+// Database tables are defined here ------------------------------------------------------
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize)]
+#[diesel(table_name = schemas::__etl_backfill)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct EtlBackfill {
+    pub id: i64,
+    pub job_id: String,
+    pub request: Value,
+    pub chunk_size: i64,
+    pub total_chunks: i64,
+    pub processed_chunks: i64,
+    pub throttle_ms: i64,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl EtlBackfill {
+    pub fn status(&self) -> BackfillStatus {
+        self.status.parse().unwrap_or(BackfillStatus::Paused)
+    }
+
+    pub fn save(&self, conn: &mut PgConnection) -> Result<Self, diesel::result::Error> {
+        use schemas::__etl_backfill::dsl::*;
+
+        let values = vec![(
+            job_id.eq(&self.job_id),
+            request.eq(&self.request),
+            chunk_size.eq(&self.chunk_size),
+            total_chunks.eq(&self.total_chunks),
+            processed_chunks.eq(&self.processed_chunks),
+            throttle_ms.eq(&self.throttle_ms),
+            status.eq(&self.status),
+        )];
+
+        diesel::insert_into(__etl_backfill)
+            .values(&values)
+            .get_result(conn)
+    }
+
+    pub fn find_by_id(
+        conn: &mut PgConnection,
+        backfill_id: i64,
+    ) -> Result<Option<Self>, diesel::result::Error> {
+        use schemas::__etl_backfill::dsl::*;
+
+        __etl_backfill
+            .filter(id.eq(backfill_id))
+            .first::<EtlBackfill>(conn)
+            .optional()
+    }
+
+    pub fn find_all(
+        conn: &mut PgConnection,
+        etl_job_id: &str,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        use schemas::__etl_backfill::dsl::*;
+
+        __etl_backfill
+            .filter(job_id.eq(etl_job_id))
+            .order(created_at.desc())
+            .load::<EtlBackfill>(conn)
+    }
+
+    pub fn find_all_running(
+        conn: &mut PgConnection,
+        etl_job_id: &str,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        use schemas::__etl_backfill::dsl::*;
+
+        __etl_backfill
+            .filter(job_id.eq(etl_job_id))
+            .filter(status.eq(BackfillStatus::Running.to_string()))
+            .order(created_at.asc())
+            .load::<EtlBackfill>(conn)
+    }
+
+    pub fn set_status(
+        conn: &mut PgConnection,
+        backfill_id: i64,
+        new_status: BackfillStatus,
+    ) -> Result<usize, diesel::result::Error> {
+        use schemas::__etl_backfill::dsl::*;
+
+        // NOTE: a finished backfill can not be paused or resumed anymore
+        diesel::update(
+            __etl_backfill
+                .filter(id.eq(backfill_id))
+                .filter(status.ne(BackfillStatus::Finished.to_string())),
+        )
+        .set((
+            status.eq(new_status.to_string()),
+            updated_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(conn)
+    }
+
+    pub fn set_throttle(
+        conn: &mut PgConnection,
+        backfill_id: i64,
+        new_throttle_ms: i64,
+    ) -> Result<usize, diesel::result::Error> {
+        use schemas::__etl_backfill::dsl::*;
+
+        diesel::update(__etl_backfill.filter(id.eq(backfill_id)))
+            .set((
+                throttle_ms.eq(new_throttle_ms),
+                updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(conn)
+    }
+
+    /// Record the number of processed chunks, finishing the backfill when all chunks are done
+    pub fn set_progress(
+        conn: &mut PgConnection,
+        backfill_id: i64,
+        new_processed_chunks: i64,
+    ) -> Result<usize, diesel::result::Error> {
+        use schemas::__etl_backfill::dsl::*;
+
+        diesel::update(__etl_backfill.filter(id.eq(backfill_id)))
+            .set((
+                processed_chunks.eq(new_processed_chunks),
+                updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+
+        diesel::update(
+            __etl_backfill
+                .filter(id.eq(backfill_id))
+                .filter(processed_chunks.ge(total_chunks)),
+        )
+        .set(status.eq(BackfillStatus::Finished.to_string()))
+        .execute(conn)
+    }
+}
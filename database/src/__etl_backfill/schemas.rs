@@ -0,0 +1,14 @@
+diesel::table! {
+    __etl_backfill (id) {
+        id -> BigSerial,
+        job_id -> VarChar,
+        request -> Jsonb,
+        chunk_size -> BigInt,
+        total_chunks -> BigInt,
+        processed_chunks -> BigInt,
+        throttle_ms -> BigInt,
+        status -> VarChar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
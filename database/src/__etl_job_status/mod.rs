@@ -1,10 +1,11 @@
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
+use serde::Serialize;
 use serde_json::Value;
 mod schemas;
 
 // Database tables are defined here ------------------------------------------------------
-#[derive(Queryable, Selectable, Insertable, Debug, Clone)]
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize)]
 #[diesel(table_name = schemas::__etl_job_status)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct EtlJobStatus {
@@ -29,12 +30,27 @@ impl EtlJobStatus {
             .load::<EtlJobStatus>(conn)
     }
 
+    pub fn find_by_id(
+        conn: &mut PgConnection,
+        etl_job_id: &str,
+        job_pk: i64,
+    ) -> Result<Option<Self>, diesel::result::Error> {
+        use schemas::__etl_job_status::dsl::*;
+
+        __etl_job_status
+            .filter(id.eq(job_pk))
+            .filter(job_id.eq(etl_job_id))
+            .first::<EtlJobStatus>(conn)
+            .optional()
+    }
+
     pub fn save(&self, conn: &mut PgConnection) -> Result<Self, diesel::result::Error> {
         use schemas::__etl_job_status::dsl::*;
 
         let values = vec![(
             job_id.eq(&self.job_id),
             active_request.eq(&self.active_request),
+            received_at.eq(&self.received_at),
         )];
 
         diesel::insert_into(__etl_job_status)
@@ -42,6 +58,13 @@ impl EtlJobStatus {
             .get_result(conn)
     }
 
+    pub fn save_all(
+        conn: &mut PgConnection,
+        jobs: &[Self],
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        conn.transaction(|conn| jobs.iter().map(|job| job.save(conn)).collect())
+    }
+
     pub fn set_job_as_finished(
         conn: &mut PgConnection,
         job_pk: i64,
@@ -0,0 +1,9 @@
+diesel::table! {
+    __etl_outbox (id) {
+        id -> BigSerial,
+        job_id -> VarChar,
+        message -> Jsonb,
+        created_at -> Timestamp,
+        sent_at -> Nullable<Timestamp>,
+    }
+}
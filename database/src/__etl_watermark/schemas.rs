@@ -0,0 +1,13 @@
+diesel::table! {
+    __etl_watermark (job_id, source_table, partition_key) {
+        job_id -> VarChar,
+        source_table -> VarChar,
+        partition_key -> VarChar,
+        watermark -> Jsonb,
+        gaps -> Jsonb,
+        updated_at -> Timestamp,
+        reorg_count -> BigInt,
+        last_reorg_depth -> BigInt,
+        max_reorg_depth -> BigInt,
+    }
+}
@@ -1,4 +1,6 @@
+mod cache;
 mod schemas;
+pub use cache::AssetCache;
 use diesel::prelude::*;
 
 // Database tables are defined here ------------------------------------------------------
@@ -15,44 +17,44 @@ pub struct Asset {
 }
 
 impl Asset {
-    pub fn save(&self, conn: &mut PgConnection) -> eyre::Result<Asset> {
+    /// Insert the asset, or return the existing one with the same address & chain
+    pub fn get_or_create(&self, conn: &mut PgConnection) -> eyre::Result<Asset> {
         use schemas::assets::dsl::*;
 
-        let values = vec![(
+        let values = (
             address.eq(&self.address),
             name.eq(&self.name),
             decimals.eq(&self.decimals),
             symbol.eq(&self.symbol),
             chain_id.eq(&self.chain_id),
-        )];
+        );
 
-        let result = diesel::insert_into(assets)
+        let inserted = diesel::insert_into(assets)
             .values(&values)
             .on_conflict((address, chain_id))
             .do_nothing()
-            .get_result(conn)?;
+            .get_result::<Asset>(conn)
+            .optional()?;
 
-        Ok(result)
+        match inserted {
+            Some(asset) => Ok(asset),
+            // NOTE: nothing is returned on conflict, the row exists
+            None => Asset::find_by_address(conn, &self.address, self.chain_id)?.ok_or_else(|| {
+                eyre::eyre!(
+                    "Asset {} of chain {} not found",
+                    self.address,
+                    self.chain_id
+                )
+            }),
+        }
     }
 
-    pub fn save_mut(&mut self, conn: &mut PgConnection) -> eyre::Result<()> {
-        use schemas::assets::dsl::*;
-
-        let values = vec![(
-            address.eq(&self.address),
-            name.eq(&self.name),
-            decimals.eq(&self.decimals),
-            symbol.eq(&self.symbol),
-            chain_id.eq(&self.chain_id),
-        )];
-
-        let result: Asset = diesel::insert_into(assets)
-            .values(&values)
-            .on_conflict((address, chain_id))
-            .do_nothing()
-            .get_result(conn)?;
+    pub fn save(&self, conn: &mut PgConnection) -> eyre::Result<Asset> {
+        self.get_or_create(conn)
+    }
 
-        self.id = result.id;
+    pub fn save_mut(&mut self, conn: &mut PgConnection) -> eyre::Result<()> {
+        *self = self.get_or_create(conn)?;
         Ok(())
     }
 
@@ -83,6 +85,14 @@ impl Asset {
         Ok(result)
     }
 
+    pub fn find_by_ids(conn: &mut PgConnection, asset_ids: &[i64]) -> eyre::Result<Vec<Asset>> {
+        use schemas::assets::dsl::*;
+
+        let result = assets.filter(id.eq_any(asset_ids)).load::<Asset>(conn)?;
+
+        Ok(result)
+    }
+
     pub fn find_by_addresses(
         conn: &mut PgConnection,
         asset_addresses: Vec<String>,
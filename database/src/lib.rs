@@ -1,15 +1,42 @@
+mod __etl_backfill;
 mod __etl_job_status;
+mod __etl_outbox;
+mod __etl_watermark;
 mod assets;
+mod migrations;
+mod notify_triggers;
+mod partition_bounds;
 mod query_interfaces;
+mod sink_writer;
+mod token_amount;
+mod transaction_stats;
 
+pub use __etl_backfill::BackfillStatus;
+pub use __etl_backfill::EtlBackfill;
 pub use __etl_job_status::EtlJobStatus;
+pub use __etl_outbox::EtlOutbox;
+pub use __etl_watermark::EtlWatermark;
 pub use assets::Asset;
+pub use assets::AssetCache;
+pub use migrations::Migrations;
+pub use notify_triggers::notify_channel;
+pub use notify_triggers::NotifyTrigger;
+pub use notify_triggers::RangeColumn;
+pub use partition_bounds::partition_bounds;
+pub use partition_bounds::PartitionBounds;
 pub use query_interfaces::*;
+pub use sink_writer::*;
+pub use token_amount::TokenAmount;
+pub use token_amount::MAX_DECIMALS;
+pub use transaction_stats::transaction_writes;
+pub use transaction_stats::TableWrites;
 
 use serde::Deserialize;
 use serde::Serialize;
 use strum::Display;
 use strum::EnumString;
+#[allow(unused_imports)]
+use strum::IntoEnumIterator;
 
 #[cfg(feature = "tier_1")]
 pub mod tier_1;
@@ -21,13 +48,21 @@ pub mod tier_2;
 pub mod tier_3;
 
 pub use diesel::pg::PgConnection;
-use diesel::prelude::*;
+pub use diesel::Connection;
 
 pub fn create_pg_connection(database_url: &str) -> PgConnection {
     PgConnection::establish(database_url)
         .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
 }
 
+/// Check the connection is usable with a trivial query
+pub fn ping(conn: &mut PgConnection) -> diesel::QueryResult<()> {
+    use diesel::RunQueryDsl;
+
+    diesel::sql_query("SELECT 1").execute(conn)?;
+    Ok(())
+}
+
 #[derive(EnumString, Debug, Clone, PartialEq, Eq, Display, Hash, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Table {
@@ -41,6 +76,65 @@ pub enum Table {
     Tier3(tier_3::Table),
 }
 
+impl Table {
+    /// All tables compiled in with the enabled tier features
+    pub fn all() -> Vec<Table> {
+        #[allow(unused_mut)]
+        let mut tables = vec![];
+
+        #[cfg(feature = "tier_1")]
+        tables.extend(tier_1::Table::iter().map(Table::Tier1));
+
+        #[cfg(feature = "tier_2")]
+        tables.extend(tier_2::Table::iter().map(Table::Tier2));
+
+        #[cfg(feature = "tier_3")]
+        tables.extend(tier_3::Table::iter().map(Table::Tier3));
+
+        tables
+    }
+
+    /// Database feature the table belongs to, eg: "tier_1"
+    pub fn tier(&self) -> String {
+        let variant = self.to_string();
+        format!("tier_{}", variant.trim_start_matches("Tier"))
+    }
+
+    /// Name of the table as it appears in messages, eg: "actions"
+    pub fn name(&self) -> String {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => name,
+            _ => unreachable!("Table is always serialized as a string"),
+        }
+    }
+
+    /// Every value of the range column is written, eg: the block numbers of the actions of a
+    /// chain, so the processed ranges of the table have no gaps. Not the case of sparse columns,
+    /// eg: the timestamps of buy-sell rows
+    pub fn contiguous_range(&self) -> bool {
+        match self {
+            #[cfg(feature = "tier_1")]
+            Table::Tier1(_) => true,
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// Check a range of the table has the range type & filters of the table's tier
+    pub fn validate(&self, query: &RangeQuery) -> eyre::Result<()> {
+        match self {
+            #[cfg(feature = "tier_1")]
+            Table::Tier1(table) => table.validate(query),
+            #[cfg(feature = "tier_2")]
+            Table::Tier2(table) => table.validate(query),
+            #[cfg(feature = "tier_3")]
+            Table::Tier3(table) => table.validate(query),
+            #[allow(unreachable_patterns)]
+            _ => Ok(()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -52,5 +146,15 @@ mod tests {
         let table_str = serde_json::to_string(&ex_table).unwrap();
         log::info!("table_str: {}", table_str);
         assert_eq!(table_str, "\"actions\"");
+        assert_eq!(ex_table.name(), "actions");
+        assert_eq!(ex_table.tier(), "tier_1");
+        assert!(ex_table.contiguous_range());
+    }
+
+    #[test]
+    #[cfg(feature = "tier_2")]
+    fn test_sparse_table_range() {
+        let buy_sell = super::Table::Tier2(crate::tier_2::Table::BuySell);
+        assert!(!buy_sell.contiguous_range());
     }
 }
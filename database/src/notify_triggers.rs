@@ -0,0 +1,226 @@
+use crate::Table;
+use diesel::connection::SimpleConnection;
+use diesel::PgConnection;
+
+/// Statement-level trigger function shared by all the tables: one notification per partition
+/// of the changed rows, carrying the `Message` consumed by the jobs.
+/// Arguments: table name, range column, range type, range column is a TIMESTAMP read as epoch
+/// seconds, partition column, list filter key & its column. Empty strings when unused.
+/// Payloads are limited to 8000 bytes: the list filter of a partition is split into several
+/// notifications of at most `max_list_bytes`, each over the range of its own rows
+const NOTIFY_FUNCTION: &str = r#"
+CREATE OR REPLACE FUNCTION __etl_notify_range(
+    table_name TEXT, kind TEXT, range_type TEXT, range_from JSONB, range_to JSONB,
+    partition_column TEXT, chain JSONB, list_key TEXT, list JSONB
+) RETURNS VOID AS $$
+DECLARE
+    filters JSONB := '{}'::JSONB;
+BEGIN
+    IF chain IS NOT NULL THEN
+        filters := filters || jsonb_build_object(partition_column, chain);
+    END IF;
+    IF list IS NOT NULL THEN
+        filters := filters || jsonb_build_object(list_key, list);
+    END IF;
+    IF filters = '{}'::JSONB THEN
+        filters := 'null'::JSONB;
+    END IF;
+
+    PERFORM pg_notify('etl_' || table_name, jsonb_build_object(kind, jsonb_build_object(
+        'table', table_name,
+        'range', jsonb_build_object(
+            'range', jsonb_build_object(range_type, jsonb_build_object('from', range_from, 'to', range_to)),
+            'filters', filters
+        )
+    ))::TEXT);
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION __etl_notify_changes() RETURNS trigger AS $$
+DECLARE
+    table_name       TEXT := TG_ARGV[0];
+    range_column     TEXT := TG_ARGV[1];
+    range_type       TEXT := TG_ARGV[2];
+    epoch            BOOLEAN := TG_ARGV[3]::BOOLEAN;
+    partition_column TEXT := TG_ARGV[4];
+    list_key         TEXT := TG_ARGV[5];
+    list_column      TEXT := TG_ARGV[6];
+    kind             TEXT := CASE TG_OP WHEN 'DELETE' THEN 'DataStoreDeleted' ELSE 'DataStoreUpdated' END;
+    -- Leaves room for the rest of the message under the 8000 bytes of a payload
+    max_list_bytes   INTEGER := 6000;
+    range_expression TEXT;
+    query            TEXT;
+    change           RECORD;
+    batch_rows       INTEGER := 0;
+    batch_chain      JSONB;
+    batch_from       JSONB;
+    batch_to         JSONB;
+    batch_list       JSONB;
+    batch_bytes      INTEGER;
+    item_bytes       INTEGER;
+BEGIN
+    range_expression := CASE WHEN epoch
+        THEN format('EXTRACT(EPOCH FROM %I)::BIGINT', range_column)
+        ELSE quote_ident(range_column) END;
+    -- One row per partition & list value, in order so a partition is notified in batches
+    query := format(
+        'SELECT %s AS chain, %s AS item, to_jsonb(MIN(%s)) AS range_from, to_jsonb(MAX(%s)) AS range_to FROM changed_rows GROUP BY 1, 2 ORDER BY 1, 2',
+        CASE WHEN partition_column = '' THEN 'NULL::JSONB' ELSE format('to_jsonb(%I)', partition_column) END,
+        CASE WHEN list_column = '' THEN 'NULL::JSONB' ELSE format('to_jsonb(%I)', list_column) END,
+        range_expression,
+        range_expression
+    );
+
+    FOR change IN EXECUTE query LOOP
+        item_bytes := COALESCE(octet_length(change.item::TEXT) + 1, 0);
+        IF batch_rows > 0 AND (change.chain IS DISTINCT FROM batch_chain
+            OR batch_bytes + item_bytes > max_list_bytes) THEN
+            PERFORM __etl_notify_range(table_name, kind, range_type, batch_from, batch_to,
+                partition_column, batch_chain, list_key, batch_list);
+            batch_rows := 0;
+        END IF;
+
+        IF batch_rows = 0 THEN
+            batch_chain := change.chain;
+            batch_from := change.range_from;
+            batch_to := change.range_to;
+            batch_list := NULL;
+            batch_bytes := 0;
+        ELSE
+            batch_from := LEAST(batch_from, change.range_from);
+            batch_to := GREATEST(batch_to, change.range_to);
+        END IF;
+        IF change.item IS NOT NULL THEN
+            batch_list := COALESCE(batch_list, '[]'::JSONB) || jsonb_build_array(change.item);
+            batch_bytes := batch_bytes + item_bytes;
+        END IF;
+        batch_rows := batch_rows + 1;
+    END LOOP;
+
+    IF batch_rows > 0 THEN
+        PERFORM __etl_notify_range(table_name, kind, range_type, batch_from, batch_to,
+            partition_column, batch_chain, list_key, batch_list);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+"#;
+
+/// Channel the changes of a table are notified on, eg: etl_actions
+pub fn notify_channel(table_name: &str) -> String {
+    format!("etl_{}", table_name)
+}
+
+/// Column of the rows a notified range is made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeColumn {
+    /// BIGINT column, eg: block_number
+    Numeric,
+    /// TIMESTAMP column notified as a numeric range of epoch seconds
+    Epoch,
+    DateTime,
+    Date,
+}
+
+/// Triggers notifying the inserted, updated & deleted rows of a tier table,
+/// so the jobs consuming it don't need a message broker
+#[derive(Debug, Clone)]
+pub struct NotifyTrigger {
+    pub table: Table,
+    /// Name of the table in the database
+    pub sql_table: String,
+    pub range_column: String,
+    pub range_type: RangeColumn,
+    /// Rows are notified per value of this column, sent as a filter, eg: chain_id
+    pub partition: Option<String>,
+    /// Filter listing the distinct values of a column of the rows, eg: ("users", "user")
+    pub list_filter: Option<(String, String)>,
+}
+
+impl NotifyTrigger {
+    /// Triggers of a compiled-in tier table, matching the ranges & filters its consumers expect
+    pub fn for_table(table: &Table) -> Option<Self> {
+        let chain_id = Some("chain_id".to_string());
+        let users = Some(("users".to_string(), "user".to_string()));
+        let (sql_table, range_column, range_type, list_filter) = match table {
+            #[cfg(feature = "tier_1")]
+            Table::Tier1(crate::tier_1::Table::Actions) => {
+                ("actions", "block_number", RangeColumn::Numeric, None)
+            }
+            #[cfg(feature = "tier_2")]
+            Table::Tier2(crate::tier_2::Table::BuySell) => {
+                ("buy_sell", "timestamp", RangeColumn::Epoch, users)
+            }
+            #[cfg(feature = "tier_3")]
+            Table::Tier3(crate::tier_3::Table::BalancePerDate) => {
+                ("balance_per_date", "date", RangeColumn::Date, users)
+            }
+            #[allow(unreachable_patterns)]
+            _ => return None,
+        };
+        Some(Self {
+            table: table.clone(),
+            sql_table: sql_table.to_string(),
+            range_column: range_column.to_string(),
+            range_type,
+            partition: chain_id,
+            list_filter,
+        })
+    }
+
+    fn trigger_name(&self, operation: &str) -> String {
+        format!("__etl_notify_{}_{}", self.sql_table, operation)
+    }
+
+    /// Install the trigger function & the triggers of the table, replacing existing ones
+    pub fn install(&self, conn: &mut PgConnection) -> eyre::Result<()> {
+        let (range_type, epoch) = match self.range_type {
+            RangeColumn::Numeric => ("numeric", false),
+            RangeColumn::Epoch => ("numeric", true),
+            RangeColumn::DateTime => ("datetime", false),
+            RangeColumn::Date => ("date", false),
+        };
+        let (list_key, list_column) = self.list_filter.clone().unwrap_or_default();
+        let arguments = [
+            self.table.name(),
+            self.range_column.clone(),
+            range_type.to_string(),
+            epoch.to_string(),
+            self.partition.clone().unwrap_or_default(),
+            list_key,
+            list_column,
+        ]
+        .iter()
+        .map(|argument| format!("'{}'", argument.replace('\'', "''")))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+        let mut sql = NOTIFY_FUNCTION.to_string();
+        for (operation, transition) in [("insert", "NEW"), ("update", "NEW"), ("delete", "OLD")] {
+            let name = self.trigger_name(operation);
+            sql.push_str(&format!(
+                "DROP TRIGGER IF EXISTS \"{name}\" ON \"{table}\";
+                 CREATE TRIGGER \"{name}\" AFTER {operation} ON \"{table}\"
+                 REFERENCING {transition} TABLE AS changed_rows
+                 FOR EACH STATEMENT EXECUTE FUNCTION __etl_notify_changes({arguments});",
+                table = self.sql_table,
+                operation = operation.to_uppercase(),
+            ));
+        }
+        conn.batch_execute(&sql)?;
+        Ok(())
+    }
+
+    pub fn uninstall(&self, conn: &mut PgConnection) -> eyre::Result<()> {
+        let mut sql = String::new();
+        for operation in ["insert", "update", "delete"] {
+            sql.push_str(&format!(
+                "DROP TRIGGER IF EXISTS \"{}\" ON \"{}\";",
+                self.trigger_name(operation),
+                self.sql_table
+            ));
+        }
+        conn.batch_execute(&sql)?;
+        Ok(())
+    }
+}
@@ -1,6 +1,10 @@
+use chrono::DateTime;
+use chrono::Datelike;
+use chrono::Duration;
 use chrono::NaiveDate;
 use chrono::NaiveDateTime;
 use diesel::PgConnection;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
 use std::fmt::Debug;
@@ -78,6 +82,16 @@ impl Range {
                 from: std::cmp::min(*from, *other_from),
                 to: std::cmp::max(*to, *other_to),
             },
+            (
+                Range::DateTime { from, to },
+                Range::DateTime {
+                    from: other_from,
+                    to: other_to,
+                },
+            ) => Range::DateTime {
+                from: std::cmp::min(*from, *other_from),
+                to: std::cmp::max(*to, *other_to),
+            },
             (
                 Range::Date { from, to },
                 Range::Date {
@@ -91,6 +105,134 @@ impl Range {
             _ => panic!("Cannot join different types of ranges"),
         }
     }
+
+    pub fn same_type(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    /// Bounds in the units of `chunk_count`: numbers, seconds or days
+    fn bounds(&self) -> (i64, i64) {
+        match self {
+            Range::Numeric { from, to } => (*from, *to),
+            Range::DateTime { from, to } => (from.and_utc().timestamp(), to.and_utc().timestamp()),
+            Range::Date { from, to } => {
+                (from.num_days_from_ce().into(), to.num_days_from_ce().into())
+            }
+        }
+    }
+
+    /// Range of the same type with other bounds, given in the units of `bounds`
+    fn with_bounds(&self, from: i64, to: i64) -> Self {
+        let datetime = |seconds: i64| {
+            DateTime::from_timestamp(seconds, 0)
+                .expect("bounds are between the bounds of a valid range")
+                .naive_utc()
+        };
+        let date = |days: i64| {
+            i32::try_from(days)
+                .ok()
+                .and_then(NaiveDate::from_num_days_from_ce_opt)
+                .expect("bounds are between the bounds of a valid range")
+        };
+        match self {
+            Range::Numeric { .. } => Range::Numeric { from, to },
+            Range::DateTime { .. } => Range::DateTime {
+                from: datetime(from),
+                to: datetime(to),
+            },
+            Range::Date { .. } => Range::Date {
+                from: date(from),
+                to: date(to),
+            },
+        }
+    }
+
+    /// Missing range between the end of this range and the start of `next`,
+    /// `None` when they overlap, touch or have different types
+    pub fn gap(&self, next: &Self) -> Option<Self> {
+        if !self.same_type(next) {
+            return None;
+        }
+        let (_, to) = self.bounds();
+        let (next_from, _) = next.bounds();
+        if next_from <= to.saturating_add(1) {
+            return None;
+        }
+        Some(self.with_bounds(to + 1, next_from - 1))
+    }
+
+    /// Parts of this range not covered by `other`
+    pub fn subtract(&self, other: &Self) -> Vec<Self> {
+        if !self.overlap(other) {
+            return vec![self.clone()];
+        }
+        let (from, to) = self.bounds();
+        let (other_from, other_to) = other.bounds();
+        let mut parts = vec![];
+        if from < other_from {
+            parts.push(self.with_bounds(from, other_from - 1));
+        }
+        if other_to < to {
+            parts.push(self.with_bounds(other_to + 1, to));
+        }
+        parts
+    }
+
+    /// Number of chunks of `size` units needed to cover the range
+    /// units are: numbers for Numeric, seconds for DateTime and days for Date.
+    /// `None` when `size` is not positive or the width of the range overflows
+    pub fn chunk_count(&self, size: i64) -> Option<i64> {
+        if size <= 0 {
+            return None;
+        }
+        let width = match self {
+            Range::Numeric { from, to } => to.checked_sub(*from)?.checked_add(1)?,
+            Range::DateTime { from, to } => (*to - *from).num_seconds() + 1,
+            Range::Date { from, to } => (*to - *from).num_days() + 1,
+        };
+        if width <= 0 {
+            return Some(0);
+        }
+        Some(width / size + i64::from(width % size > 0))
+    }
+
+    /// Return the chunk at `index` when splitting the range into chunks of `size` units
+    pub fn chunk(&self, size: i64, index: i64) -> Option<Self> {
+        if index < 0 || index >= self.chunk_count(size)? {
+            return None;
+        }
+
+        // NOTE: the chunk is in the range, only its end may go past the supported values
+        let offset = size.checked_mul(index)?;
+        let chunk = match self {
+            Range::Numeric { from, to } => {
+                let from = from.checked_add(offset)?;
+                Range::Numeric {
+                    from,
+                    to: std::cmp::min(from.saturating_add(size - 1), *to),
+                }
+            }
+            Range::DateTime { from, to } => {
+                let from = from.checked_add_signed(Duration::try_seconds(offset)?)?;
+                let end = Duration::try_seconds(size - 1)
+                    .and_then(|width| from.checked_add_signed(width));
+                Range::DateTime {
+                    from,
+                    to: end.map_or(*to, |end| std::cmp::min(end, *to)),
+                }
+            }
+            Range::Date { from, to } => {
+                let from = from.checked_add_signed(Duration::try_days(offset)?)?;
+                let end =
+                    Duration::try_days(size - 1).and_then(|width| from.checked_add_signed(width));
+                Range::Date {
+                    from,
+                    to: end.map_or(*to, |end| std::cmp::min(end, *to)),
+                }
+            }
+        };
+        Some(chunk)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
@@ -112,6 +254,56 @@ impl RangeQuery {
             filters: self.filters.clone(),
         }
     }
+
+    /// Filters of the range read as the filter type of its table
+    pub fn filters_as<T: DeserializeOwned>(&self) -> eyre::Result<T> {
+        if !self.filters.is_object() {
+            eyre::bail!("Filters must be a JSON object, got: {}", self.filters);
+        }
+        serde_json::from_value(self.filters.clone())
+            .map_err(|error| eyre::eyre!("Invalid filters {}: {}", self.filters, error))
+    }
+}
+
+/// Filters of the per-user tables: `{"chain_id": 1, "user": "0x.."}`, or
+/// `{"chain_id": 1, "users": [..]}` for the rows of several users changed in one range.
+/// Without `asset_id`, the rows of all the assets match
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct UserFilter {
+    pub chain_id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<String>,
+}
+
+impl UserFilter {
+    /// Filters of a range of the given users on the chain, the single user form when there is only one
+    pub fn for_users(chain_id: i64, mut users: Vec<String>) -> serde_json::Value {
+        users.sort();
+        users.dedup();
+        let (user, users) = match <[String; 1]>::try_from(users) {
+            Ok([user]) => (Some(user), vec![]),
+            Err(users) => (None, users),
+        };
+        serde_json::json!(UserFilter {
+            chain_id,
+            asset_id: None,
+            user,
+            users,
+        })
+    }
+
+    pub fn users(&self) -> eyre::Result<Vec<String>> {
+        let mut users = self.users.clone();
+        users.extend(self.user.clone());
+        if users.is_empty() {
+            eyre::bail!("No user filter");
+        }
+        Ok(users)
+    }
 }
 
 pub trait RowStream {
@@ -138,4 +330,100 @@ mod tests {
         let r = Range::Date { from: d1, to: d3 };
         assert!(r.validate());
     }
+
+    #[test]
+    fn test_range_chunks() {
+        let r = Range::Numeric { from: 1, to: 25 };
+        assert_eq!(r.chunk_count(10), Some(3));
+        assert_eq!(r.chunk(10, 0), Some(Range::Numeric { from: 1, to: 10 }));
+        assert_eq!(r.chunk(10, 2), Some(Range::Numeric { from: 21, to: 25 }));
+        assert_eq!(r.chunk(10, 3), None);
+        assert_eq!(r.chunk_count(0), None);
+
+        // Chunks ending past i64::MAX
+        assert_eq!(r.chunk_count(i64::MAX), Some(1));
+        assert_eq!(r.chunk(i64::MAX, 0), Some(r.clone()));
+        let r = Range::Numeric {
+            from: 1,
+            to: i64::MAX,
+        };
+        assert_eq!(r.chunk_count(i64::MAX), Some(1));
+        assert_eq!(r.chunk_count(2), Some(i64::MAX / 2 + 1));
+        assert_eq!(
+            r.chunk(2, i64::MAX / 2),
+            Some(Range::Numeric {
+                from: i64::MAX,
+                to: i64::MAX,
+            })
+        );
+        // Wider than i64::MAX
+        let r = Range::Numeric {
+            from: 0,
+            to: i64::MAX,
+        };
+        assert_eq!(r.chunk_count(10), None);
+        assert_eq!(r.chunk(10, 0), None);
+
+        let d1 = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let d2 = chrono::NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let r = Range::Date { from: d1, to: d2 };
+        assert_eq!(r.chunk_count(7), Some(2));
+        assert_eq!(r.chunk(i64::MAX, 0), Some(r.clone()));
+        assert_eq!(
+            r.chunk(7, 1),
+            Some(Range::Date {
+                from: chrono::NaiveDate::from_ymd_opt(2024, 1, 8).unwrap(),
+                to: d2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_range_gaps() {
+        let r = Range::Numeric { from: 1, to: 10 };
+        assert_eq!(r.gap(&Range::Numeric { from: 11, to: 20 }), None);
+        assert_eq!(r.gap(&Range::Numeric { from: 5, to: 20 }), None);
+        assert_eq!(
+            r.gap(&Range::Numeric { from: 15, to: 20 }),
+            Some(Range::Numeric { from: 11, to: 14 })
+        );
+        assert_eq!(
+            Range::Numeric {
+                from: 1,
+                to: i64::MAX
+            }
+            .gap(&Range::Numeric { from: 5, to: 6 }),
+            None
+        );
+
+        assert_eq!(
+            r.subtract(&Range::Numeric { from: 4, to: 6 }),
+            vec![
+                Range::Numeric { from: 1, to: 3 },
+                Range::Numeric { from: 7, to: 10 }
+            ]
+        );
+        assert_eq!(r.subtract(&Range::Numeric { from: 0, to: 20 }), vec![]);
+        assert_eq!(
+            r.subtract(&Range::Numeric { from: 11, to: 20 }),
+            vec![r.clone()]
+        );
+
+        let date = |day| chrono::NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+        let r = Range::Date {
+            from: date(1),
+            to: date(3),
+        };
+        assert_eq!(
+            r.gap(&Range::Date {
+                from: date(6),
+                to: date(9),
+            }),
+            Some(Range::Date {
+                from: date(4),
+                to: date(5),
+            })
+        );
+        assert_eq!(r.gap(&Range::Numeric { from: 10, to: 20 }), None);
+    }
 }
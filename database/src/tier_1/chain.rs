@@ -0,0 +1,122 @@
+use crate::Range;
+use crate::RangeQuery;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Blocks >= `from_block` of `chain_id` were replaced, announced by the source as a
+/// `DataStoreInvalidated` message of `actions` with the range of `ChainReorg::range`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainReorg {
+    pub chain_id: i64,
+    pub from_block: i64,
+}
+
+impl ChainReorg {
+    /// Open block range from `from_block`, filtered on the chain
+    pub fn range(&self) -> RangeQuery {
+        RangeQuery {
+            range: Range::Numeric {
+                from: self.from_block,
+                to: i64::MAX,
+            },
+            filters: serde_json::json!({ "chain_id": self.chain_id }),
+        }
+    }
+
+    pub fn from_range(query: &RangeQuery) -> eyre::Result<Self> {
+        let Range::Numeric { from, to: _ } = query.range else {
+            eyre::bail!("Invalid range type, a reorg is a block range");
+        };
+        let filter: super::ChainIdFilter = serde_json::from_value(query.filters.clone())
+            .map_err(|error| eyre::eyre!("No chain_id filter in the reorg range: {}", error))?;
+        Ok(Self {
+            chain_id: filter.chain_id,
+            from_block: from,
+        })
+    }
+}
+
+/// Last processed block & reorgs seen on a chain
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ChainHead {
+    pub head_block: i64,
+    pub reorg_count: u64,
+    /// Number of processed blocks replaced by the last reorg
+    pub last_reorg_depth: i64,
+    pub max_reorg_depth: i64,
+}
+
+/// Heads & reorg depths per chain, kept in the state of the jobs deriving data from block ranges
+#[derive(Debug, Clone, Default)]
+pub struct ChainHeads {
+    chains: BTreeMap<i64, ChainHead>,
+}
+
+impl ChainHeads {
+    pub fn get(&self, chain_id: i64) -> Option<&ChainHead> {
+        self.chains.get(&chain_id)
+    }
+
+    /// Record blocks up to `block_number` as processed
+    pub fn advance(&mut self, chain_id: i64, block_number: i64) {
+        let head = self.chains.entry(chain_id).or_default();
+        head.head_block = head.head_block.max(block_number);
+    }
+
+    /// Roll the head back before the replaced blocks, return the reorg depth
+    pub fn reorg(&mut self, reorg: &ChainReorg) -> i64 {
+        let head = self.chains.entry(reorg.chain_id).or_default();
+        let depth = (head.head_block - reorg.from_block + 1).max(0);
+        head.head_block = head.head_block.min(reorg.from_block - 1);
+        head.reorg_count += 1;
+        head.last_reorg_depth = depth;
+        head.max_reorg_depth = head.max_reorg_depth.max(depth);
+        depth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_reorg() {
+        let reorg = ChainReorg {
+            chain_id: 1,
+            from_block: 100,
+        };
+        assert_eq!(ChainReorg::from_range(&reorg.range()).unwrap(), reorg);
+        assert!(ChainReorg::from_range(&RangeQuery::default()).is_err());
+
+        let mut heads = ChainHeads::default();
+        heads.advance(1, 105);
+        heads.advance(1, 103);
+        heads.advance(2, 50);
+        assert_eq!(heads.get(1).unwrap().head_block, 105);
+
+        assert_eq!(heads.reorg(&reorg), 6);
+        assert_eq!(
+            heads.get(1),
+            Some(&ChainHead {
+                head_block: 99,
+                reorg_count: 1,
+                last_reorg_depth: 6,
+                max_reorg_depth: 6,
+            })
+        );
+        assert_eq!(heads.get(2).unwrap().head_block, 50);
+
+        // Blocks not processed yet
+        heads.advance(1, 101);
+        assert_eq!(
+            heads.reorg(&ChainReorg {
+                chain_id: 1,
+                from_block: 200
+            }),
+            0
+        );
+        assert_eq!(heads.get(1).unwrap().head_block, 101);
+        assert_eq!(heads.get(1).unwrap().max_reorg_depth, 6);
+    }
+}
@@ -17,10 +17,19 @@ CREATE TABLE IF NOT EXISTS actions
     created_at      TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
 );
 */
+pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "action_types"))]
+    pub struct ActionTypes;
+}
+
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ActionTypes;
+
     actions (id) {
         id -> VarChar,
-        action_type -> VarChar,
+        action_type -> ActionTypes,
         asset_id -> BigInt,
         asset_value -> Numeric,
         usd_value -> Numeric,
@@ -32,6 +41,6 @@ diesel::table! {
         data -> Nullable<Jsonb>,
         block_number -> BigInt,
         block_timestamp -> BigInt,
-        created_at -> Timestamp,
+        created_at -> Nullable<Timestamptz>,
     }
 }
@@ -1,7 +1,11 @@
 diesel::table! {
-    buy_sell (user, timestamp) {
+    buy_sell (user, chain_id, asset_id, timestamp) {
         user -> VarChar,
-        amount -> BigInt,
+        chain_id -> BigInt,
+        asset_id -> BigInt,
+        amount -> Numeric,
         timestamp -> Timestamp,
+        from_block -> BigInt,
+        to_block -> BigInt,
     }
 }
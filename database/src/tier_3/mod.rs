@@ -3,10 +3,16 @@ mod schemas;
 use crate::Range;
 use crate::RangeQuery;
 use crate::RowStream;
+use crate::SinkWriter;
+use crate::TokenAmount;
+use crate::UserFilter;
 use chrono::NaiveDate;
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::query_builder::QueryFragment;
 use serde::Deserialize;
 use serde::Serialize;
+use strum::EnumIter;
 use strum::EnumString;
 
 // Database tables are defined here ------------------------------------------------------
@@ -15,27 +21,80 @@ use strum::EnumString;
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct BalancePerDate {
     pub user: String,
-    pub balance: i64,
+    pub chain_id: i64,
+    pub asset_id: i64,
+    pub balance: TokenAmount,
     pub date: NaiveDate,
 }
 
 impl BalancePerDate {
-    pub fn insert_many(pool: &mut PgConnection, rows: Vec<Self>) -> eyre::Result<()> {
-        use diesel::pg::upsert::excluded;
+    /// Latest balance of the user in the asset of the chain before `before_date`
+    pub fn balance_before(
+        pool: &mut PgConnection,
+        user_address: &str,
+        (chain, asset): (i64, i64),
+        before_date: NaiveDate,
+    ) -> eyre::Result<Option<Self>> {
         use schemas::balance_per_date::dsl::*;
 
-        let inserted: Vec<Self> = diesel::insert_into(balance_per_date)
-            .values(&rows)
-            .on_conflict((user, date))
-            .do_update()
-            .set(balance.eq(excluded(balance)))
-            .get_results(pool)?;
-        log::info!("Inserted {} rows", inserted.len());
-        Ok(())
+        let row = balance_per_date
+            .filter(user.eq(user_address))
+            .filter(chain_id.eq(chain))
+            .filter(asset_id.eq(asset))
+            .filter(date.lt(before_date))
+            .order(date.desc())
+            .first(pool)
+            .optional()?;
+        Ok(row)
+    }
+
+    /// Delete the balances of the user in the asset of the chain at the given dates,
+    /// return the number of deleted rows
+    pub fn delete_dates(
+        pool: &mut PgConnection,
+        user_address: &str,
+        (chain, asset): (i64, i64),
+        dates: &[NaiveDate],
+    ) -> eyre::Result<usize> {
+        use schemas::balance_per_date::dsl::*;
+
+        let deleted = diesel::delete(balance_per_date)
+            .filter(user.eq(user_address))
+            .filter(chain_id.eq(chain))
+            .filter(asset_id.eq(asset))
+            .filter(date.eq_any(dates))
+            .execute(pool)?;
+        log::info!("Deleted {} rows", deleted);
+        Ok(deleted)
+    }
+}
+
+impl SinkWriter for BalancePerDate {
+    type Table = schemas::balance_per_date::table;
+
+    const TABLE: &'static str = "balance_per_date";
+    const COLUMNS: &'static [&'static str] = &["user", "chain_id", "asset_id", "balance", "date"];
+    const CONFLICT: &'static [&'static str] = &["user", "chain_id", "asset_id", "date"];
+    const UPDATE: &'static [&'static str] = &["balance"];
+
+    fn insert(rows: &[Self]) -> impl QueryFragment<Pg> + '_ {
+        diesel::insert_into(schemas::balance_per_date::table).values(rows)
+    }
+
+    fn copy_row(&self) -> Vec<Option<String>> {
+        vec![
+            Some(self.user.clone()),
+            Some(self.chain_id.to_string()),
+            Some(self.asset_id.to_string()),
+            Some(self.balance.to_string()),
+            Some(self.date.to_string()),
+        ]
     }
 }
 
-#[derive(EnumString, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, Hash)]
+#[derive(
+    EnumString, EnumIter, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, Hash,
+)]
 #[serde(rename_all = "lowercase")]
 pub enum Table {
     #[strum(ascii_case_insensitive)]
@@ -43,27 +102,43 @@ pub enum Table {
     BalancePerDate,
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct Filter {
-    pub user: String,
+impl Table {
+    /// Check a range of the table has the range type & filters its consumers expect
+    pub fn validate(&self, query: &RangeQuery) -> eyre::Result<()> {
+        match self {
+            Table::BalancePerDate => {
+                let Range::Date { .. } = query.range else {
+                    eyre::bail!("Range of balanceperdate must be dates");
+                };
+                query.filters_as::<UserFilter>()?.users()?;
+            }
+        }
+        Ok(())
+    }
 }
 
 // Implement RowStream for BalancePerDate -------------------------------------------------------
 impl RowStream for BalancePerDate {
     fn query(pool: &mut PgConnection, query: &RangeQuery) -> eyre::Result<Vec<Self>> {
-        let user_filter: Filter = serde_json::from_value(query.filters.clone())?;
+        let filter: UserFilter = serde_json::from_value(query.filters.clone())?;
+        let users = filter.users()?;
         if let Range::Date {
             from: from_date,
-            to: _,
+            to: to_date,
         } = query.range
         {
             use schemas::balance_per_date::dsl::*;
-            let rows = balance_per_date
-                .filter(user.eq(user_filter.user))
+            let mut rows = balance_per_date
+                .filter(chain_id.eq(filter.chain_id))
+                .filter(user.eq_any(users))
                 .filter(date.ge(from_date))
-                .order((date, date.asc()))
-                .load(pool)?;
-            Ok(rows)
+                .filter(date.le(to_date))
+                .order((user.asc(), asset_id.asc(), date.asc()))
+                .into_boxed();
+            if let Some(filter_asset_id) = filter.asset_id {
+                rows = rows.filter(asset_id.eq(filter_asset_id));
+            }
+            Ok(rows.load(pool)?)
         } else {
             Err(eyre::eyre!("Invalid range type"))
         }
@@ -1,7 +1,9 @@
 diesel::table! {
-    balance_per_date (user, date) {
+    balance_per_date (user, chain_id, asset_id, date) {
         user -> VarChar,
-        balance -> BigInt,
+        chain_id -> BigInt,
+        asset_id -> BigInt,
+        balance -> Numeric,
         date -> Date,
     }
 }
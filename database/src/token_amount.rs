@@ -0,0 +1,298 @@
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::num_bigint::Sign;
+use bigdecimal::BigDecimal;
+use bigdecimal::ToPrimitive;
+use bigdecimal::Zero;
+use diesel::deserialize::FromSql;
+use diesel::deserialize::FromSqlRow;
+use diesel::expression::AsExpression;
+use diesel::pg::Pg;
+use diesel::pg::PgValue;
+use diesel::serialize::Output;
+use diesel::serialize::ToSql;
+use diesel::sql_types::Numeric;
+use std::cmp::Ordering;
+
+/// Largest number of digits after the point of a NUMERIC
+pub const MAX_DECIMALS: u32 = 16383;
+/// Largest number of digits before the point of a NUMERIC
+const MAX_INTEGER_DIGITS: u64 = 131072;
+
+/// Exact amount of a token: integer base units & the decimals of the asset, eg: 1.5 USDT is
+/// 1_500_000 units with 6 decimals.
+/// Stored as NUMERIC, the decimals round-trip as the scale of the value. Amounts with different
+/// decimals are aligned on the larger ones, which never loses precision
+#[derive(Debug, Clone, Default, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Numeric)]
+pub struct TokenAmount {
+    units: BigInt,
+    decimals: u32,
+}
+
+impl TokenAmount {
+    pub fn zero(decimals: u32) -> Self {
+        Self {
+            units: BigInt::zero(),
+            decimals,
+        }
+    }
+
+    pub fn from_units(units: impl Into<BigInt>, decimals: u32) -> Self {
+        Self {
+            units: units.into(),
+            decimals,
+        }
+    }
+
+    /// Amount of `raw` base units of an asset with `decimals`, eg: a NUMERIC column holding
+    /// on-chain values. Fractional base units are kept as extra decimals
+    pub fn from_raw(raw: &BigDecimal, decimals: u32) -> eyre::Result<Self> {
+        let (units, scale) = raw.as_bigint_and_exponent();
+        let amount = if scale >= 0 {
+            let extra =
+                u32::try_from(scale).map_err(|_| eyre::eyre!("Too many decimals in {}", raw))?;
+            Self {
+                units,
+                decimals: decimals
+                    .checked_add(extra)
+                    .ok_or_else(|| eyre::eyre!("Too many decimals in {}", raw))?,
+            }
+        } else {
+            let exponent = u32::try_from(-scale)
+                .map_err(|_| eyre::eyre!("Exponent out of range in {}", raw))?;
+            Self {
+                units: units * pow10(exponent),
+                decimals,
+            }
+        };
+        amount.checked()
+    }
+
+    /// Amount of a NUMERIC value, its scale gives the decimals
+    pub fn from_decimal(value: &BigDecimal) -> eyre::Result<Self> {
+        Self::from_raw(value, 0)
+    }
+
+    pub fn units(&self) -> &BigInt {
+        &self.units
+    }
+
+    pub fn decimals(&self) -> u32 {
+        self.decimals
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.units.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.units.sign() == Sign::Minus
+    }
+
+    pub fn to_decimal(&self) -> BigDecimal {
+        BigDecimal::new(self.units.clone(), self.decimals.into())
+    }
+
+    /// Whole units of the token, rounded toward zero, `None` past the i64 range
+    pub fn to_i64(&self) -> Option<i64> {
+        (&self.units / pow10(self.decimals)).to_i64()
+    }
+
+    /// Same amount with `decimals`, fails when digits would be dropped
+    pub fn rescale(&self, decimals: u32) -> eyre::Result<Self> {
+        if decimals >= self.decimals {
+            return Self {
+                units: &self.units * pow10(decimals - self.decimals),
+                decimals,
+            }
+            .checked();
+        }
+
+        let divisor = pow10(self.decimals - decimals);
+        if !(&self.units % &divisor).is_zero() {
+            eyre::bail!(
+                "Rescaling {} to {} decimals loses precision",
+                self,
+                decimals
+            );
+        }
+        Ok(Self {
+            units: &self.units / divisor,
+            decimals,
+        })
+    }
+
+    pub fn checked_add(&self, other: &Self) -> eyre::Result<Self> {
+        let (left, right, decimals) = self.aligned(other);
+        Self {
+            units: left + right,
+            decimals,
+        }
+        .checked()
+    }
+
+    pub fn checked_sub(&self, other: &Self) -> eyre::Result<Self> {
+        let (left, right, decimals) = self.aligned(other);
+        Self {
+            units: left - right,
+            decimals,
+        }
+        .checked()
+    }
+
+    pub fn checked_sum<'a>(
+        amounts: impl IntoIterator<Item = &'a TokenAmount>,
+        decimals: u32,
+    ) -> eyre::Result<Self> {
+        amounts
+            .into_iter()
+            .try_fold(Self::zero(decimals), |sum, amount| sum.checked_add(amount))
+    }
+
+    /// Units of both amounts on their larger decimals
+    fn aligned(&self, other: &Self) -> (BigInt, BigInt, u32) {
+        match self.decimals.cmp(&other.decimals) {
+            Ordering::Equal => (self.units.clone(), other.units.clone(), self.decimals),
+            Ordering::Less => (
+                &self.units * pow10(other.decimals - self.decimals),
+                other.units.clone(),
+                other.decimals,
+            ),
+            Ordering::Greater => (
+                self.units.clone(),
+                &other.units * pow10(self.decimals - other.decimals),
+                self.decimals,
+            ),
+        }
+    }
+
+    /// Fail on the amounts a NUMERIC can not store
+    fn checked(self) -> eyre::Result<Self> {
+        if self.decimals > MAX_DECIMALS {
+            eyre::bail!(
+                "{} decimals, NUMERIC stores up to {}",
+                self.decimals,
+                MAX_DECIMALS
+            );
+        }
+        // NOTE: 3.33 bits per digit, the digits are only counted for the huge amounts
+        let max_bits = (MAX_INTEGER_DIGITS + u64::from(self.decimals)) * 10 / 3;
+        if self.units.bits() > max_bits {
+            let digits = self.units.magnitude().to_string().len() as u64;
+            if digits > MAX_INTEGER_DIGITS + u64::from(self.decimals) {
+                eyre::bail!("Amount overflows NUMERIC: {} digits", digits);
+            }
+        }
+        Ok(self)
+    }
+}
+
+fn pow10(exponent: u32) -> BigInt {
+    BigInt::from(10).pow(exponent)
+}
+
+impl std::ops::Neg for TokenAmount {
+    type Output = TokenAmount;
+
+    fn neg(self) -> Self::Output {
+        Self {
+            units: -self.units,
+            decimals: self.decimals,
+        }
+    }
+}
+
+impl PartialEq for TokenAmount {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TokenAmount {}
+
+impl PartialOrd for TokenAmount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TokenAmount {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (left, right, _) = self.aligned(other);
+        left.cmp(&right)
+    }
+}
+
+/// Plain notation with all the decimals, eg: -1.500000
+impl std::fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = self.units.magnitude().to_string();
+        let sign = if self.is_negative() { "-" } else { "" };
+        let decimals = self.decimals as usize;
+        if decimals == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+
+        let digits = format!("{:0>width$}", digits, width = decimals + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - decimals);
+        write!(f, "{}{}.{}", sign, integer, fraction)
+    }
+}
+
+impl ToSql<Numeric, Pg> for TokenAmount {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> diesel::serialize::Result {
+        <BigDecimal as ToSql<Numeric, Pg>>::to_sql(&self.to_decimal(), &mut out.reborrow())
+    }
+}
+
+impl FromSql<Numeric, Pg> for TokenAmount {
+    fn from_sql(bytes: PgValue<'_>) -> diesel::deserialize::Result<Self> {
+        let value = <BigDecimal as FromSql<Numeric, Pg>>::from_sql(bytes)?;
+        Ok(Self::from_decimal(&value).map_err(|error| error.to_string())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_token_amount() {
+        let usdt = TokenAmount::from_units(1_500_000, 6);
+        assert_eq!(usdt.to_string(), "1.500000");
+        assert_eq!((-TokenAmount::from_units(5, 3)).to_string(), "-0.005");
+        assert_eq!(usdt.to_i64(), Some(1));
+
+        // 18 decimals tokens overflow i64 base units, not TokenAmount
+        let eth = TokenAmount::from_raw(&BigDecimal::from_str("25000000000000000000").unwrap(), 18)
+            .unwrap();
+        assert_eq!(eth.to_string(), "25.000000000000000000");
+        assert!(eth.units().to_i64().is_none());
+
+        let sum = eth.checked_add(&usdt).unwrap();
+        assert_eq!(sum.decimals(), 18);
+        assert_eq!(sum.to_string(), "26.500000000000000000");
+        assert_eq!(sum.checked_sub(&usdt).unwrap(), eth);
+        assert_eq!(
+            TokenAmount::checked_sum([&usdt, &usdt], 0).unwrap(),
+            TokenAmount::from_units(3, 0)
+        );
+
+        // Equal values with different decimals
+        assert_eq!(usdt, TokenAmount::from_units(15, 1));
+        assert!(usdt < eth);
+
+        assert_eq!(usdt.rescale(2).unwrap().to_string(), "1.50");
+        assert!(usdt.rescale(0).is_err());
+
+        // Fractional base units are kept
+        let raw = TokenAmount::from_raw(&BigDecimal::from_str("12.5").unwrap(), 6).unwrap();
+        assert_eq!(raw.to_string(), "0.0000125");
+
+        assert!(TokenAmount::zero(MAX_DECIMALS + 1).checked().is_err());
+        assert!(TokenAmount::from_units(1, 0)
+            .rescale(MAX_DECIMALS + 1)
+            .is_err());
+    }
+}
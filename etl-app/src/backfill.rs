@@ -0,0 +1,149 @@
+use crate::shutdown::Shutdown;
+use common::messages::Message;
+use common::ETLTrait;
+use database::BackfillStatus;
+use database::EtlBackfill;
+use database::RangeQuery;
+use database::Table;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Payload to create a backfill, from the admin API or the `backfill` subcommand
+#[derive(Debug, Deserialize, Clone)]
+pub struct BackfillRequest {
+    pub table: Table,
+    pub range: RangeQuery,
+    pub chunk_size: i64,
+    #[serde(default)]
+    pub throttle_ms: i64,
+}
+
+impl BackfillRequest {
+    /// Register the backfill once its range is a valid message of the job, as the ones of
+    /// `/process`: its chunks would fail otherwise
+    pub fn submit(self, etl: &dyn ETLTrait) -> eyre::Result<EtlBackfill> {
+        let msg = Message::DataStoreUpdated {
+            table: self.table.clone(),
+            range: self.range.clone(),
+        };
+        etl.validate_message(&msg)?;
+
+        etl.job_manager()
+            .create_backfill(self.table, self.range, self.chunk_size, self.throttle_ms)
+    }
+}
+
+/// Progress of a backfill as reported by the admin API
+pub fn backfill_report(backfill: &EtlBackfill) -> serde_json::Value {
+    let progress = if backfill.total_chunks > 0 {
+        backfill.processed_chunks as f64 / backfill.total_chunks as f64
+    } else {
+        1.0
+    };
+
+    let mut report = serde_json::to_value(backfill).unwrap_or_default();
+    report["progress"] = serde_json::json!(progress);
+    report
+}
+
+/// Run the backfills of a job chunk by chunk. Progress is stored after every chunk,
+/// so paused or interrupted backfills continue from the last processed chunk
+pub struct BackfillRunner {
+    etl: Arc<dyn ETLTrait>,
+    poll_interval: Duration,
+    shutdown: Shutdown,
+}
+
+impl BackfillRunner {
+    pub fn new(etl: Arc<dyn ETLTrait>, poll_interval: Duration, shutdown: Shutdown) -> Self {
+        Self {
+            etl,
+            poll_interval,
+            shutdown,
+        }
+    }
+
+    /// Run the backfills until the shutdown, the current chunk is finished first
+    pub async fn run(&self) -> eyre::Result<()> {
+        while !self.shutdown.is_requested() {
+            for backfill in self.etl.job_manager().running_backfills()? {
+                let backfill_id = backfill.id;
+                if let Err(error) = self.run_backfill(backfill).await {
+                    log::error!("Backfill {} failed, pausing it: {:?}", backfill_id, error);
+                    self.etl
+                        .job_manager()
+                        .set_backfill_status(backfill_id, BackfillStatus::Paused)?;
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(self.poll_interval) => {},
+                _ = self.shutdown.requested() => {},
+            }
+        }
+        Ok(())
+    }
+
+    async fn run_backfill(&self, backfill: EtlBackfill) -> eyre::Result<()> {
+        let job_manager = self.etl.job_manager();
+        let (table, query) = match serde_json::from_value(backfill.request.clone())? {
+            Message::DataStoreUpdated { table, range } => (table, range),
+            other => eyre::bail!("Backfill of a {} message is not supported", other.kind()),
+        };
+
+        let mut processed = backfill.processed_chunks;
+        while processed < backfill.total_chunks {
+            if self.shutdown.is_requested() {
+                log::info!(
+                    "Backfill {} stopped by the shutdown at chunk {}, resumed on restart",
+                    backfill.id,
+                    processed
+                );
+                return Ok(());
+            }
+            // NOTE: reload to pick up pause & throttle changes made through the admin API
+            let Some(current) = job_manager.backfill(backfill.id)? else {
+                return Ok(());
+            };
+            if current.status() != BackfillStatus::Running {
+                log::info!(
+                    "Backfill {} is {}, stop processing",
+                    current.id,
+                    current.status
+                );
+                return Ok(());
+            }
+
+            let chunk = query
+                .range
+                .chunk(backfill.chunk_size, processed)
+                .ok_or_else(|| eyre::eyre!("Chunk {} out of range", processed))?;
+
+            let msg = Message::DataStoreUpdated {
+                table: table.clone(),
+                range: RangeQuery {
+                    range: chunk,
+                    filters: query.filters.clone(),
+                },
+            };
+            self.etl.process_message_from_mq(msg).await?;
+
+            processed += 1;
+            job_manager.set_backfill_progress(backfill.id, processed)?;
+            log::info!(
+                "Backfill {}: {}/{} chunks processed",
+                backfill.id,
+                processed,
+                backfill.total_chunks
+            );
+
+            if current.throttle_ms > 0 {
+                tokio::time::sleep(Duration::from_millis(current.throttle_ms as u64)).await;
+            }
+        }
+
+        log::info!("Backfill {} finished", backfill.id);
+        Ok(())
+    }
+}
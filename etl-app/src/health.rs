@@ -0,0 +1,94 @@
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// State of the message queue of a job, reported by the queue client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueStatus {
+    /// Connecting, or reconnecting after a connection loss
+    #[default]
+    Disconnected,
+    /// Connected without consumer, eg: publishing the last messages on shutdown
+    Connected,
+    /// Connected & consuming the job queue
+    Consuming,
+    /// No queue, eg: in dry-run mode
+    Disabled,
+}
+
+#[derive(Default)]
+struct State {
+    queue: QueueStatus,
+    resumed: bool,
+    /// Start of the message the job is processing
+    processing_since: Option<Instant>,
+}
+
+/// Health of a job, reported by its tasks and checked by the probes of the admin API
+#[derive(Clone, Default)]
+pub struct JobHealth(Arc<Mutex<State>>);
+
+impl JobHealth {
+    /// Health of a job processing the requests of the admin API only
+    pub fn without_queue() -> Self {
+        let health = Self::default();
+        health.set_queue_status(QueueStatus::Disabled);
+        health.set_resumed();
+        health
+    }
+
+    pub fn set_queue_status(&self, status: QueueStatus) {
+        self.0.lock().unwrap().queue = status;
+    }
+
+    pub fn queue_status(&self) -> QueueStatus {
+        self.0.lock().unwrap().queue
+    }
+
+    /// The unfinished requests saved before the last stop are processed
+    pub fn set_resumed(&self) {
+        self.0.lock().unwrap().resumed = true;
+    }
+
+    pub fn is_resumed(&self) -> bool {
+        self.0.lock().unwrap().resumed
+    }
+
+    pub fn start_processing(&self) {
+        self.0.lock().unwrap().processing_since = Some(Instant::now());
+    }
+
+    pub fn finish_processing(&self) {
+        self.0.lock().unwrap().processing_since = None;
+    }
+
+    /// Time spent on the current message while `backlog` messages wait for it, none when the
+    /// job waits for messages or nothing waits for it
+    pub fn stalled_for(&self, backlog: usize) -> Option<Duration> {
+        let since = self.0.lock().unwrap().processing_since?;
+        (backlog > 0).then(|| since.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stalled_for() {
+        let health = JobHealth::default();
+        // Waiting for messages
+        assert_eq!(health.stalled_for(3), None);
+
+        health.start_processing();
+        // Nothing waits for the message in progress
+        assert_eq!(health.stalled_for(0), None);
+        assert!(health.stalled_for(3).is_some());
+
+        health.finish_processing();
+        assert_eq!(health.stalled_for(3), None);
+    }
+}
@@ -1,17 +1,41 @@
+mod backfill;
+mod health;
+mod jobs;
+mod migrate;
 mod mq;
+mod outbox;
+mod poller;
 mod server;
+mod shutdown;
 
+use backfill::BackfillRequest;
+use backfill::BackfillRunner;
 use clap::Parser;
-use common::messages::Message;
+use clap::Subcommand;
+use common::topology::Topology;
 use common::ETLTrait;
+use database::RangeQuery;
 
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use health::JobHealth;
+use jobs::Config;
+use jobs::Defaults;
 use kanal::AsyncReceiver;
+use mq::Incoming;
 use mq::MessageQueue;
 use mq::MessageQueueTrait;
+use mq::QueueArgs;
+use outbox::OutboxRelay;
+use poller::Poller;
+use server::ApiArgs;
+use server::JobHandle;
 use server::Server;
-
-#[cfg(feature = "action_job")]
-use action_job::Etl;
+use shutdown::Shutdown;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -39,14 +63,173 @@ struct Args {
 
     #[arg(long, env = "ETL_SERVER_PORT", default_value = "8080")]
     port: u16,
+
+    /// ETL jobs to run, repeat or separate by comma. Default to jobs in config, or all compiled-in jobs
+    #[arg(long = "job", env = "ETL_JOBS", value_delimiter = ',')]
+    jobs: Vec<String>,
+
+    /// JSON file with per-job connection overrides
+    #[arg(long, env = "ETL_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Interval in seconds between checks for running backfills
+    #[arg(long, env = "ETL_BACKFILL_POLL_INTERVAL", default_value = "5")]
+    backfill_poll_interval: u64,
+
+    /// Messages buffered between the message queue & the processing of a job, and between the
+    /// outbox & the queue. The queue stops receiving while the buffer is full
+    #[arg(long, env = "ETL_CHANNEL_CAPACITY", default_value = "100")]
+    channel_capacity: usize,
+
+    /// Interval in seconds between checks for unpublished messages of the outbox,
+    /// completed jobs wake the relay up immediately
+    #[arg(long, env = "ETL_OUTBOX_POLL_INTERVAL", default_value = "5")]
+    outbox_poll_interval: u64,
+
+    /// Seconds given to the jobs to finish the messages in progress & publish their output
+    /// on SIGTERM or SIGINT, before exiting with an error
+    #[arg(long, env = "ETL_SHUTDOWN_TIMEOUT", default_value = "30")]
+    shutdown_timeout: u64,
+
+    /// Seconds a job may spend on a message while others wait for it, before `/livez` reports
+    /// it as stalled
+    #[arg(long, env = "ETL_STALL_TIMEOUT", default_value = "300")]
+    stall_timeout: u64,
+
+    /// Run handlers in rolled back sink transactions and log what they would write & emit.
+    /// Nothing is consumed from the queue, published or marked as completed
+    #[arg(long, env = "ETL_DRY_RUN")]
+    dry_run: bool,
+
+    /// Apply pending migrations at startup instead of refusing to start
+    #[arg(long, env = "ETL_AUTO_MIGRATE")]
+    auto_migrate: bool,
+
+    #[command(flatten)]
+    queue: QueueArgs,
+
+    #[command(flatten, next_help_heading = "Admin API")]
+    api: ApiArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the pipeline DAG of all compiled-in jobs, its validation and the queue bindings
+    Topology,
+    /// Apply the pending migrations: job-manager tables and the tiers produced by the jobs
+    Migrate,
+    /// Install triggers notifying the changes of the source tables of the jobs, for --pg-notify.
+    /// Tables produced by a compiled-in job are left out, the job publishes their messages
+    NotifyTriggers {
+        /// Remove the triggers instead
+        #[arg(long)]
+        drop: bool,
+    },
+    /// Register a backfill: the range is split into chunks processed one by one by the running app
+    Backfill {
+        /// Table to reprocess, eg: actions
+        #[arg(long)]
+        table: String,
+        /// Range as JSON, eg: '{"numeric": {"from": 1, "to": 1000000}}'
+        #[arg(long)]
+        range: String,
+        /// Filters as JSON, eg: '{"chain_id": 1}'
+        #[arg(long, default_value = "null")]
+        filters: String,
+        /// Size of every chunk: numbers, seconds or days depending on the range type
+        #[arg(long, default_value = "10000")]
+        chunk_size: i64,
+        /// Pause between two chunks in milliseconds
+        #[arg(long, default_value = "0")]
+        throttle_ms: i64,
+    },
 }
 
-async fn main_task(etl: Etl, receiver: AsyncReceiver<Message>) -> eyre::Result<()> {
-    while let Ok(msg) = receiver.recv().await {
-        etl.process_message_from_mq(msg).await?;
+fn describe_topology(topology: &Topology) -> serde_json::Value {
+    serde_json::json!({
+        "jobs": topology.jobs,
+        "report": topology.validate(&database::Table::all()),
+        "bindings": topology.bindings(),
+    })
+}
+
+fn create_backfill(
+    job: &jobs::JobSettings,
+    table: String,
+    range: String,
+    filters: String,
+    chunk_size: i64,
+    throttle_ms: i64,
+) -> eyre::Result<()> {
+    let request = BackfillRequest {
+        table: serde_json::from_value(serde_json::Value::String(table))?,
+        range: RangeQuery {
+            range: serde_json::from_str(&range)?,
+            filters: serde_json::from_str(&filters)?,
+        },
+        chunk_size,
+        throttle_ms,
+    };
+
+    let etl = job
+        .registration
+        .create(&job.source, &job.sink, &job.job_manager, &job.shard)?;
+    let backfill = request.submit(etl.as_ref())?;
+    let report = backfill::backfill_report(&backfill);
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// Process the messages of a job until the shutdown, the message in progress is finished first
+async fn main_task(
+    etl: Arc<dyn ETLTrait>,
+    receiver: AsyncReceiver<Incoming>,
+    dry_run: bool,
+    shutdown: Shutdown,
+    health: JobHealth,
+) -> eyre::Result<()> {
+    loop {
+        let incoming = tokio::select! {
+            biased;
+            _ = shutdown.requested() => break,
+            incoming = receiver.recv() => match incoming {
+                Ok(incoming) => incoming,
+                // NOTE: the queue may see the shutdown and disconnect before this task does
+                Err(_) if shutdown.is_requested() => break,
+                Err(_) => eyre::bail!("Message queue receiver exited unexpectedly"),
+            },
+        };
+
+        let msg = incoming.message.clone();
+        health.start_processing();
+        if dry_run {
+            let report = etl.dry_run(msg).await?;
+            log::info!("Dry-run report: {}", serde_json::to_string_pretty(&report)?);
+        } else if let Some(job_pk) = incoming.job_pk {
+            etl.process_message(msg, job_pk).await?;
+        } else {
+            etl.process_message_from_mq(msg).await?;
+        }
+        incoming.acknowledge().await;
+        health.finish_processing();
     }
 
-    eyre::bail!("Message queue receiver exited unexpectedly")
+    // NOTE: messages not processed yet are rejected, the queue delivers them again
+    let mut rejected = 0;
+    while let Ok(Some(incoming)) = receiver.try_recv() {
+        drop(incoming);
+        rejected += 1;
+    }
+    receiver.close();
+    log::info!(
+        "Job {} stopped, {} messages rejected",
+        etl.job_manager().job_id(),
+        rejected
+    );
+    Ok(())
 }
 
 #[tokio::main]
@@ -58,23 +241,207 @@ async fn main() -> eyre::Result<()> {
         source,
         sink,
         job_manager,
+        jobs,
+        config,
+        backfill_poll_interval,
+        outbox_poll_interval,
+        channel_capacity,
+        shutdown_timeout,
+        stall_timeout,
+        dry_run,
+        auto_migrate,
+        queue,
+        api,
+        command,
     } = Args::parse();
+
+    let registry = jobs::registered_jobs();
+    let topology = Topology::from_registry(&registry);
+    let description = describe_topology(&topology);
+
+    if let Some(Command::Topology) = command {
+        println!("{}", serde_json::to_string_pretty(&description)?);
+        return Ok(());
+    }
+
+    let report = topology.validate(&database::Table::all());
+    if !report.is_valid() {
+        eyre::bail!("Pipeline topology has cycles: {:?}", report.cycles);
+    }
+    if !report.orphan_tables.is_empty() {
+        log::warn!("Tables not used by any job: {:?}", report.orphan_tables);
+    }
+
     log::info!("Binding port: {}", port);
 
-    let msg_queue = MessageQueue::new(&Etl::id()).await?;
-    let server = Server::new(port);
+    let config = match config {
+        Some(path) => Config::load(&path)?,
+        None => Config::default(),
+    };
+    let defaults = Defaults {
+        source: &source,
+        sink: &sink,
+        job_manager: &job_manager,
+    };
+    let selected_jobs = jobs::select_jobs(&registry, &jobs, &config, &defaults)?;
+
+    if let Some(Command::Migrate) = command {
+        migrate::run(&selected_jobs)?;
+        println!("Database schemas are up to date");
+        return Ok(());
+    }
+    if let Some(Command::NotifyTriggers { drop }) = command {
+        migrate::notify_triggers(&selected_jobs, &report.source_tables, drop)?;
+        return Ok(());
+    }
+    migrate::verify(&selected_jobs, auto_migrate)?;
+
+    if let Some(Command::Backfill {
+        table,
+        range,
+        filters,
+        chunk_size,
+        throttle_ms,
+    }) = command
+    {
+        let [job] = selected_jobs.as_slice() else {
+            eyre::bail!("Select the job to backfill with --job, and the config of a single shard");
+        };
+        return create_backfill(job, table, range, filters, chunk_size, throttle_ms);
+    }
+
+    let shutdown = Shutdown::on_signal()?;
+    let server = Server::new(port, dry_run, Duration::from_secs(stall_timeout), api);
+    let mut handles = HashMap::new();
+    let mut tasks: Vec<BoxFuture<eyre::Result<()>>> = vec![];
 
-    let (input_sender, input_receiver) = kanal::unbounded_async();
-    let (output_sender, output_receiver) = kanal::unbounded_async();
+    for job in selected_jobs {
+        log::info!("Starting job: {}", job.scope());
+        let (input_sender, input_receiver) = kanal::bounded_async(channel_capacity);
 
-    let etl = Etl::new(&source, &sink, &job_manager, output_sender)?;
-    etl.resume().await?;
+        let etl = job
+            .registration
+            .create(&job.source, &job.sink, &job.job_manager, &job.shard)?;
 
-    tokio::try_join!(
-        msg_queue.run(input_sender.clone(), output_receiver),
-        main_task(etl, input_receiver),
-        server.run(input_sender.clone())
-    )?;
+        let health = match dry_run {
+            true => JobHealth::without_queue(),
+            false => JobHealth::default(),
+        };
+        handles.insert(
+            job.scope(),
+            JobHandle {
+                sender: input_sender.clone(),
+                etl: etl.clone(),
+                health: health.clone(),
+            },
+        );
 
-    panic!("App exited unexpectedly")
+        if dry_run {
+            // NOTE: only requests sent to the admin API are dry-run, the queue is left untouched
+            log::info!("Job {} is running in dry-run mode", job.scope());
+            tasks.push(main_task(etl, input_receiver, true, shutdown.clone(), health).boxed());
+            continue;
+        }
+
+        let binding = topology
+            .binding(job.id(), &job.shard)
+            .ok_or_else(|| eyre::eyre!("No queue binding for job: {}", job.id()))?;
+        let msg_queue = MessageQueue::new(&queue, &binding, &job.source, &job.sink).await?;
+
+        let (output_sender, output_receiver) = kanal::bounded_async(channel_capacity);
+        let outbox_relay = OutboxRelay::new(etl.clone(), Duration::from_secs(outbox_poll_interval));
+        let backfill_runner = BackfillRunner::new(
+            etl.clone(),
+            Duration::from_secs(backfill_poll_interval),
+            shutdown.clone(),
+        );
+        let poller = job
+            .poll
+            .map(|config| {
+                Poller::new(
+                    etl.clone(),
+                    &job.source,
+                    config,
+                    input_sender.clone(),
+                    shutdown.clone(),
+                )
+            })
+            .transpose()?;
+        let shutdown = shutdown.clone();
+        let (stop_publishing, processing_stopped) = Shutdown::channel();
+        tasks.push(
+            async move {
+                let processing = async {
+                    // NOTE: the server & the queue start meanwhile, `/readyz` waits for it
+                    health.start_processing();
+                    etl.resume().await?;
+                    health.finish_processing();
+                    health.set_resumed();
+                    tokio::try_join!(
+                        main_task(etl, input_receiver, false, shutdown.clone(), health.clone()),
+                        backfill_runner.run(),
+                        async {
+                            match poller {
+                                Some(poller) => poller.run().await,
+                                None => Ok(()),
+                            }
+                        },
+                    )?;
+                    // NOTE: the outbox is published once no job can complete anymore
+                    stop_publishing.request();
+                    Ok::<_, eyre::Report>(())
+                };
+                tokio::try_join!(
+                    msg_queue.run(
+                        input_sender,
+                        output_receiver,
+                        shutdown.clone(),
+                        health.clone()
+                    ),
+                    processing,
+                    outbox_relay.run(output_sender, processing_stopped),
+                )?;
+                Ok(())
+            }
+            .boxed(),
+        );
+    }
+
+    tasks.push(server.run(handles, description, shutdown.clone()).boxed());
+    let mut app = futures::future::try_join_all(tasks);
+    tokio::select! {
+        result = &mut app => {
+            result?;
+        }
+        _ = shutdown.requested() => {
+            let timeout = Duration::from_secs(shutdown_timeout);
+            match tokio::time::timeout(timeout, &mut app).await {
+                Ok(result) => {
+                    result?;
+                }
+                Err(_) => eyre::bail!(
+                    "Shutdown timed out after {:?}, unfinished jobs are resumed on restart",
+                    timeout
+                ),
+            }
+        }
+    }
+
+    if !shutdown.is_requested() {
+        eyre::bail!("App exited unexpectedly")
+    }
+    log::info!("Shutdown complete");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_args() {
+        // NOTE: flattened queue arguments must not clash with the app arguments
+        Args::command().debug_assert();
+    }
 }
@@ -0,0 +1,147 @@
+use crate::jobs::JobSettings;
+use database::Connection;
+use database::Migrations;
+use database::NotifyTrigger;
+use database::PgConnection;
+
+/// Migrations owned by a database of a job
+struct MigrationTarget {
+    /// eg: "sink of job_id_abc", connection strings are not logged
+    label: String,
+    database_url: String,
+    migrations: Migrations,
+}
+
+/// Job-manager tables go to the job-manager database, and the tiers of the produced tables
+/// to the sink. Source tables are owned by the upstream job writing them
+fn targets(jobs: &[JobSettings]) -> Vec<MigrationTarget> {
+    let mut targets: Vec<MigrationTarget> = vec![];
+    for job in jobs {
+        let mut job_targets = vec![(
+            format!("job-manager of {}", job.id()),
+            &job.job_manager,
+            Migrations::job_manager(),
+        )];
+        for migrations in Migrations::for_tables(&job.registration.produces) {
+            job_targets.push((format!("sink of {}", job.id()), &job.sink, migrations));
+        }
+
+        for (label, database_url, migrations) in job_targets {
+            let exists = targets.iter().any(|target| {
+                target.database_url == *database_url && target.migrations == migrations
+            });
+            if !exists {
+                targets.push(MigrationTarget {
+                    label,
+                    database_url: database_url.clone(),
+                    migrations,
+                });
+            }
+        }
+    }
+    targets
+}
+
+fn connect(target: &MigrationTarget) -> eyre::Result<PgConnection> {
+    PgConnection::establish(&target.database_url)
+        .map_err(|error| eyre::eyre!("Failed to connect to the {}: {}", target.label, error))
+}
+
+/// Apply the pending migrations of the jobs
+pub fn run(jobs: &[JobSettings]) -> eyre::Result<()> {
+    for target in targets(jobs) {
+        let mut conn = connect(&target)?;
+        for name in target.migrations.pending_row_deletions(&mut conn)? {
+            log::warn!(
+                "Migration {} deletes the rows of its table on the {}, backfill it once applied",
+                name,
+                target.label
+            );
+        }
+        let applied = target.migrations.run_pending(&mut conn)?;
+        log::info!(
+            "Applied {} {} migration(s) on the {}: {:?}",
+            applied.len(),
+            target.migrations.name(),
+            target.label,
+            applied
+        );
+    }
+    Ok(())
+}
+
+/// Refuse to start when a database of the jobs has pending migrations, unless `auto_migrate`.
+/// Migrations deleting rows are only applied by the `migrate` command
+pub fn verify(jobs: &[JobSettings], auto_migrate: bool) -> eyre::Result<()> {
+    let mut outdated = vec![];
+    let mut deleting_rows = vec![];
+    for target in targets(jobs) {
+        let mut conn = connect(&target)?;
+        for name in target.migrations.pending_row_deletions(&mut conn)? {
+            deleting_rows.push(format!("{} on the {}", name, target.label));
+        }
+        let pending = target.migrations.pending(&mut conn)?;
+        if !pending.is_empty() {
+            outdated.push(format!(
+                "{} migrations on the {}: {:?}",
+                target.migrations.name(),
+                target.label,
+                pending
+            ));
+        }
+    }
+
+    if outdated.is_empty() {
+        return Ok(());
+    }
+
+    if auto_migrate && !deleting_rows.is_empty() {
+        eyre::bail!(
+            "Pending migrations delete rows, run the `migrate` command then backfill the tables: {:?}",
+            deleting_rows
+        );
+    }
+    if auto_migrate {
+        log::info!("Applying pending migrations: {:?}", outdated);
+        return run(jobs);
+    }
+
+    eyre::bail!(
+        "Database schemas are behind this build, run the `migrate` command or start with --auto-migrate. Pending: {:?}",
+        outdated
+    )
+}
+
+/// Install or drop the notification triggers of the consumed tables in `source_tables`,
+/// on the source database of every job
+pub fn notify_triggers(
+    jobs: &[JobSettings],
+    source_tables: &[String],
+    drop: bool,
+) -> eyre::Result<()> {
+    for job in jobs {
+        let tables = job
+            .registration
+            .consumes
+            .iter()
+            .filter(|table| source_tables.contains(&table.name()));
+        for table in tables {
+            let trigger = NotifyTrigger::for_table(table)
+                .ok_or_else(|| eyre::eyre!("No notification trigger for {}", table.name()))?;
+            let mut conn = PgConnection::establish(&job.source).map_err(|error| {
+                eyre::eyre!("Failed to connect to the source of {}: {}", job.id(), error)
+            })?;
+            if drop {
+                trigger.uninstall(&mut conn)?;
+                println!("Dropped the notification triggers of {}", trigger.sql_table);
+            } else {
+                trigger.install(&mut conn)?;
+                println!(
+                    "Installed the notification triggers of {}",
+                    trigger.sql_table
+                );
+            }
+        }
+    }
+    Ok(())
+}
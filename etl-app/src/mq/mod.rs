@@ -1,4 +1,7 @@
+use crate::health::JobHealth;
+use crate::shutdown::Shutdown;
 use common::messages::Message;
+use common::topology::QueueBinding;
 
 #[cfg(feature = "google-cloud-pubsub")]
 use google_cloud_pubsub::client::Client as PubSubClient;
@@ -8,16 +11,25 @@ use google_cloud_pubsub::client::ClientConfig as PubSubClientConfig;
 #[cfg(feature = "amqprs")]
 mod rabbitmq;
 #[cfg(feature = "amqprs")]
-use rabbitmq::Args as RabbitMQArgs;
-#[cfg(feature = "amqprs")]
 use rabbitmq::RabbitMQ;
+#[cfg(feature = "amqprs")]
+use rabbitmq::RabbitMQArgs;
+
+#[cfg(feature = "pg_notify")]
+mod pg_notify;
+#[cfg(feature = "pg_notify")]
+use pg_notify::PgNotify;
+#[cfg(feature = "pg_notify")]
+use pg_notify::PgNotifyArgs;
 
 use async_trait::async_trait;
 use clap::Parser;
 use kanal::AsyncReceiver;
 use kanal::AsyncSender;
+use kanal::OneshotAsyncReceiver;
+use kanal::OneshotAsyncSender;
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Parser, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct PubSubArgs {
     /// Google Cloud PubSub project ID
@@ -37,7 +49,7 @@ pub struct PubSubArgs {
     pub refresh_token: String,
 }
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Parser, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct QueueArgs {
     #[cfg(feature = "google-cloud-pubsub")]
@@ -46,6 +58,74 @@ pub struct QueueArgs {
     #[cfg(feature = "amqprs")]
     #[command(flatten, next_help_heading = "RabbitMQ client")]
     pub rabbitmq: RabbitMQArgs,
+    #[cfg(feature = "pg_notify")]
+    #[command(flatten, next_help_heading = "Postgres notifications")]
+    pub pg_notify: PgNotifyArgs,
+}
+
+/// Input message of a job. A message delivered by the queue is acknowledged once processed,
+/// and rejected back to the queue when it is dropped unprocessed, eg: on shutdown
+pub struct Incoming {
+    pub message: Message,
+    /// Etl-job the message is already saved as, eg: a request of the admin API
+    pub job_pk: Option<i64>,
+    processed: Option<OneshotAsyncSender<()>>,
+}
+
+impl Incoming {
+    /// Message without a delivery to acknowledge, eg: polled or notified by Postgres
+    pub fn new(message: Message) -> Self {
+        Self {
+            message,
+            job_pk: None,
+            processed: None,
+        }
+    }
+
+    /// Message saved as the etl-job `job_pk`, unfinished jobs are resumed on restart
+    pub fn saved(message: Message, job_pk: i64) -> Self {
+        Self {
+            message,
+            job_pk: Some(job_pk),
+            processed: None,
+        }
+    }
+
+    /// Message delivered by the queue, the receiver resolves once it is processed
+    pub fn delivered(message: Message) -> (Self, OneshotAsyncReceiver<()>) {
+        let (processed, receiver) = kanal::oneshot_async();
+        let incoming = Self {
+            message,
+            job_pk: None,
+            processed: Some(processed),
+        };
+        (incoming, receiver)
+    }
+
+    pub async fn acknowledge(self) {
+        if let Some(processed) = self.processed {
+            processed.send(()).await.ok();
+        }
+    }
+}
+
+/// Output message to publish, see `crate::outbox::OutboxRelay`. The queue acknowledges it once
+/// it is published, and drops it unacknowledged when publishing failed
+pub struct Outgoing {
+    pub message: Message,
+    published: OneshotAsyncSender<()>,
+}
+
+impl Outgoing {
+    pub fn new(message: Message) -> (Self, OneshotAsyncReceiver<()>) {
+        let (published, receiver) = kanal::oneshot_async();
+        (Self { message, published }, receiver)
+    }
+
+    pub async fn acknowledge(self) {
+        // NOTE: the relay may have given up waiting, nothing to notify then
+        self.published.send(()).await.ok();
+    }
 }
 
 /*
@@ -56,23 +136,47 @@ pub enum MessageQueue {
     PubSub(PubSubClient),
     #[cfg(feature = "amqprs")]
     RabbitMQ(RabbitMQ),
+    #[cfg(feature = "pg_notify")]
+    PgNotify(PgNotify),
 }
 
 #[async_trait]
 pub trait MessageQueueTrait {
+    /// Consume the job queue into `source_sender` and publish the messages of `sink_receiver`.
+    /// On shutdown, stop consuming, publish until `sink_receiver` is disconnected,
+    /// then close the connection and return. The connection & consumer state is reported
+    /// to `health`
     async fn run(
         &self,
-        source_sender: AsyncSender<Message>,
-        sink_receiver: AsyncReceiver<Message>,
+        source_sender: AsyncSender<Incoming>,
+        sink_receiver: AsyncReceiver<Outgoing>,
+        shutdown: Shutdown,
+        health: JobHealth,
     ) -> eyre::Result<()>;
 }
 
 impl MessageQueue {
-    pub async fn new(job_id: &str) -> eyre::Result<Self> {
+    /// Queue of a job, Postgres notifications are received from its source & published on its sink
+    #[cfg_attr(not(feature = "pg_notify"), allow(unused_variables))]
+    pub async fn new(
+        args: &QueueArgs,
+        binding: &QueueBinding,
+        source: &str,
+        sink: &str,
+    ) -> eyre::Result<Self> {
+        #[cfg(feature = "pg_notify")]
+        if args.pg_notify.pg_notify
+            || cfg!(not(any(
+                feature = "google-cloud-pubsub",
+                feature = "amqprs"
+            )))
+        {
+            log::info!("Using Postgres notifications for job: {}", binding.job_id);
+            return Ok(MessageQueue::PgNotify(PgNotify::new(source, sink, binding)));
+        }
+
         #[cfg(feature = "google-cloud-pubsub")]
         {
-            let QueueArgs { pubsub } = QueueArgs::parse();
-            let pubsub = pubsub.unwrap();
             let config = PubSubClientConfig::default().with_auth().await?;
             let client = PubSubClient::new(config).await?;
             return Ok(MessageQueue::PubSub(client));
@@ -80,11 +184,13 @@ impl MessageQueue {
 
         #[cfg(feature = "amqprs")]
         {
-            log::info!("Using RabbitMQ");
-            let QueueArgs { rabbitmq: args } = QueueArgs::parse();
-            let client = RabbitMQ::new(&args, job_id).await?;
-            return Ok(MessageQueue::RabbitMQ(client));
+            log::info!("Using RabbitMQ for job: {}", binding.job_id);
+            let client = RabbitMQ::new(&args.rabbitmq, binding).await?;
+            Ok(MessageQueue::RabbitMQ(client))
         }
+
+        #[cfg(not(any(feature = "google-cloud-pubsub", feature = "amqprs")))]
+        eyre::bail!("No message queue compiled in, enable a message queue feature")
     }
 }
 
@@ -92,8 +198,10 @@ impl MessageQueue {
 impl MessageQueueTrait for MessageQueue {
     async fn run(
         &self,
-        source_sender: AsyncSender<Message>,
-        sink_receiver: AsyncReceiver<Message>,
+        source_sender: AsyncSender<Incoming>,
+        sink_receiver: AsyncReceiver<Outgoing>,
+        shutdown: Shutdown,
+        health: JobHealth,
     ) -> eyre::Result<()> {
         match self {
             #[cfg(feature = "google-cloud-pubsub")]
@@ -102,7 +210,17 @@ impl MessageQueueTrait for MessageQueue {
                 unimplemented!()
             }
             #[cfg(feature = "amqprs")]
-            MessageQueue::RabbitMQ(client) => client.run(source_sender, sink_receiver).await,
+            MessageQueue::RabbitMQ(client) => {
+                client
+                    .run(source_sender, sink_receiver, shutdown, health)
+                    .await
+            }
+            #[cfg(feature = "pg_notify")]
+            MessageQueue::PgNotify(client) => {
+                client
+                    .run(source_sender, sink_receiver, shutdown, health)
+                    .await
+            }
         }
     }
 }
@@ -1,8 +1,18 @@
+use super::Incoming;
 use super::MessageQueueTrait;
+use super::Outgoing;
 
+use crate::health::JobHealth;
+use crate::health::QueueStatus;
+use crate::shutdown::Shutdown;
+use amqprs::callbacks::ChannelCallback;
 use amqprs::callbacks::DefaultConnectionCallback;
+use amqprs::channel::BasicCancelArguments;
 use amqprs::channel::BasicConsumeArguments;
+use amqprs::channel::BasicNackArguments;
 use amqprs::channel::BasicPublishArguments;
+use amqprs::channel::BasicQosArguments;
+use amqprs::channel::ConfirmSelectArguments;
 use amqprs::channel::ExchangeDeclareArguments;
 use amqprs::channel::QueueBindArguments;
 use amqprs::channel::QueueDeclareArguments;
@@ -10,41 +20,121 @@ use amqprs::channel::{BasicAckArguments, Channel};
 use amqprs::connection::Connection;
 use amqprs::connection::OpenConnectionArguments;
 use amqprs::consumer::AsyncConsumer;
+use amqprs::Ack;
 use amqprs::BasicProperties;
+use amqprs::Cancel;
+use amqprs::CloseChannel;
 use amqprs::Deliver;
+use amqprs::Nack;
+use amqprs::Return;
 use async_trait::async_trait;
 use clap::Parser;
 use common::messages::Message;
+use common::topology::QueueBinding;
 use eyre::Result;
 use kanal::AsyncReceiver;
 use kanal::AsyncSender;
+#[cfg(feature = "rabbitmq_tls")]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
 use tokio::select;
+use tokio::task::JoinSet;
+use tokio_retry::strategy::ExponentialBackoff;
+
+type AmqpResult<T> = std::result::Result<T, amqprs::error::Error>;
+
+/// Longest wait for the broker to confirm a published message
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval between checks of the connection & channels
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Parser, Clone)]
 #[command(author, version, about, long_about = None)]
-pub struct Args {
+pub struct RabbitMQArgs {
     #[arg(long, env = "RABBITMQ_EXCHANGE", default_value = "etl")]
     pub exchange: String,
-    #[arg(long, env = "RABBITMQ_SOURCE_QUEUE", default_value = "etl_tier_2")]
-    pub source_queue: String,
-    #[arg(long, env = "RABBITMQ_SINK_QUEUE", default_value = "etl_tier_3")]
-    pub sink_queue: String,
     #[arg(long, env = "RABBITMQ_HOST", default_value = "localhost")]
     pub host: String,
+    #[arg(
+        id = "rabbitmq_port",
+        long = "rabbitmq-port",
+        env = "RABBITMQ_PORT",
+        default_value = "5672"
+    )]
+    pub port: u16,
+    #[arg(long = "rabbitmq-vhost", env = "RABBITMQ_VHOST", default_value = "/")]
+    pub vhost: String,
     #[arg(long, env = "RABBITMQ_USERNAME", default_value = "guest")]
     pub username: String,
     #[arg(long, env = "RABBITMQ_PASSWORD", default_value = "guest")]
     pub password: String,
+    /// Connect with TLS, the server certificate is checked against `host`
+    #[cfg(feature = "rabbitmq_tls")]
+    #[arg(long = "rabbitmq-tls", env = "RABBITMQ_TLS")]
+    pub tls: bool,
+    /// PEM file of the CA signing the server certificate, default to the webpki roots
+    #[cfg(feature = "rabbitmq_tls")]
+    #[arg(long = "rabbitmq-ca-cert", env = "RABBITMQ_CA_CERT")]
+    pub ca_cert: Option<PathBuf>,
+    /// Unacknowledged messages the broker delivers to the consumer at once
+    #[arg(
+        long = "rabbitmq-prefetch",
+        env = "RABBITMQ_PREFETCH",
+        default_value = "10"
+    )]
+    pub prefetch: u16,
+    /// Publishing attempts of a message nacked or not confirmed by the broker before reconnecting
+    #[arg(
+        long = "rabbitmq-publish-retries",
+        env = "RABBITMQ_PUBLISH_RETRIES",
+        default_value = "3"
+    )]
+    pub publish_retries: usize,
+    /// Longest delay in seconds between two reconnection attempts, the delay doubles from 1s
+    #[arg(
+        long = "rabbitmq-max-reconnect-delay",
+        env = "RABBITMQ_MAX_RECONNECT_DELAY",
+        default_value = "60"
+    )]
+    pub max_reconnect_delay: u64,
 }
 
+impl RabbitMQArgs {
+    fn connection_arguments(&self) -> Result<OpenConnectionArguments> {
+        let mut args =
+            OpenConnectionArguments::new(&self.host, self.port, &self.username, &self.password);
+        args.virtual_host(&self.vhost);
+
+        #[cfg(feature = "rabbitmq_tls")]
+        if self.tls {
+            let adaptor = amqprs::tls::TlsAdaptor::without_client_auth(
+                self.ca_cert.as_deref(),
+                self.host.clone(),
+            )?;
+            args.tls_adaptor(adaptor);
+        }
+
+        Ok(args)
+    }
+}
+
+/// RabbitMQ client of a job: consumes the job queue and publishes the output messages with
+/// publisher confirms. The connection, channels, queue bindings & consumer are set up again
+/// after a connection loss, with an exponential backoff
 pub struct RabbitMQ {
-    connection: Connection,
-    args: Args,
-    client_name: String,
+    args: RabbitMQArgs,
+    binding: QueueBinding,
 }
 
+/// Tasks acknowledging the deliveries once the job processed them
+type Acknowledgements = Arc<Mutex<JoinSet<()>>>;
+
 struct RabbitMqConsumer {
-    sender: AsyncSender<Message>,
+    sender: AsyncSender<Incoming>,
+    acks: Acknowledgements,
 }
 
 #[async_trait]
@@ -56,123 +146,406 @@ impl AsyncConsumer for RabbitMqConsumer {
         _basic_properties: BasicProperties,
         content: Vec<u8>,
     ) {
-        let message = String::from_utf8(content).unwrap();
+        let delivery_tag = delivery.delivery_tag();
+        let message = String::from_utf8_lossy(&content);
         log::info!("Received message: {}", message);
-        if let Ok(msg) = serde_json::from_str::<Message>(&message) {
-            log::info!("Valid message found: {}", msg);
-            self.sender.send(msg).await.unwrap();
-        }
+        let Ok(msg) = serde_json::from_str::<Message>(&message) else {
+            log::warn!("Dropping invalid message: {}", message);
+            let ack = BasicAckArguments::new(delivery_tag, false);
+            if let Err(error) = channel.basic_ack(ack).await {
+                log::error!("Failed to acknowledge message: {}", error);
+            }
+            return;
+        };
+        log::info!("Valid message found: {}", msg);
 
-        // FIXME: Acknowledge the message, only after processing it
-        channel
-            .basic_ack(BasicAckArguments::new(delivery.delivery_tag(), false))
-            .await
-            .expect("Failed to acknowledge message");
+        // NOTE: acknowledge once processed, messages the job did not process are rejected
+        // back to the queue, eg: on shutdown, and delivered again
+        let (incoming, processed) = Incoming::delivered(msg);
+        let sent = self.sender.send(incoming).await.is_ok();
+        let channel = channel.clone();
+        let mut acks = self.acks.lock().unwrap();
+        while acks.try_join_next().is_some() {}
+        acks.spawn(async move {
+            let result = match sent && processed.recv().await.is_ok() {
+                true => {
+                    let ack = BasicAckArguments::new(delivery_tag, false);
+                    channel.basic_ack(ack).await
+                }
+                false => {
+                    let nack = BasicNackArguments::new(delivery_tag, false, true);
+                    channel.basic_nack(nack).await
+                }
+            };
+            if let Err(error) = result {
+                // NOTE: the broker delivers the message again once the channel is closed
+                log::error!("Failed to acknowledge message: {}", error);
+            }
+        });
     }
 }
 
+/// Broker confirmation of the messages published up to a delivery tag
+#[derive(Debug)]
+struct Confirm {
+    delivery_tag: u64,
+    acked: bool,
+}
+
+/// Forward the publisher confirms of a channel to the publishing task
+struct ConfirmCallback {
+    sender: AsyncSender<Confirm>,
+}
+
+#[async_trait]
+impl ChannelCallback for ConfirmCallback {
+    async fn close(&mut self, channel: &Channel, close: CloseChannel) -> AmqpResult<()> {
+        log::error!("RabbitMQ closed channel {}: {}", channel, close);
+        Ok(())
+    }
+
+    async fn cancel(&mut self, channel: &Channel, cancel: Cancel) -> AmqpResult<()> {
+        log::warn!(
+            "RabbitMQ cancelled consumer on channel {}: {:?}",
+            channel,
+            cancel
+        );
+        Ok(())
+    }
+
+    async fn flow(&mut self, channel: &Channel, active: bool) -> AmqpResult<bool> {
+        log::warn!("RabbitMQ flow on channel {}: active={}", channel, active);
+        Ok(true)
+    }
+
+    async fn publish_ack(&mut self, _channel: &Channel, ack: Ack) {
+        let confirm = Confirm {
+            delivery_tag: ack.delivery_tag(),
+            acked: true,
+        };
+        self.sender.send(confirm).await.ok();
+    }
+
+    async fn publish_nack(&mut self, _channel: &Channel, nack: Nack) {
+        let confirm = Confirm {
+            delivery_tag: nack.delivery_tag(),
+            acked: false,
+        };
+        self.sender.send(confirm).await.ok();
+    }
+
+    async fn publish_return(
+        &mut self,
+        channel: &Channel,
+        ret: Return,
+        _basic_properties: BasicProperties,
+        _content: Vec<u8>,
+    ) {
+        log::warn!(
+            "RabbitMQ returned a message on channel {}: {}",
+            channel,
+            ret
+        );
+    }
+}
+
+/// Connection to the broker with the channels of the job
+struct Session {
+    connection: Connection,
+    consumer: Channel,
+    /// Consumer subscribed to the job queue, none once the shutdown is requested
+    consumer_tag: Option<String>,
+    acks: Acknowledgements,
+    publisher: Channel,
+    confirms: AsyncReceiver<Confirm>,
+    /// Delivery tag of the last message published, counted by the broker per channel from 1
+    delivery_tag: u64,
+}
+
 impl RabbitMQ {
-    pub async fn new(args: &Args, client_name: &str) -> Result<Self> {
+    pub async fn new(args: &RabbitMQArgs, binding: &QueueBinding) -> Result<Self> {
         log::info!(
-            "Connecting to RabbitMQ: source={}, sink={}",
-            args.source_queue,
-            args.sink_queue
+            "Connecting to RabbitMQ {}:{}{}: queue={}, routing keys={:?}",
+            args.host,
+            args.port,
+            args.vhost,
+            binding.queue,
+            binding.routing_keys
         );
-        let conn_args =
-            OpenConnectionArguments::new(&args.host, 5672, &args.username, &args.password);
-        let connection = Connection::open(&conn_args).await?;
-        connection
-            .register_callback(DefaultConnectionCallback)
-            .await?;
+        // NOTE: fail fast on a wrong configuration, later connection losses are retried
+        args.connection_arguments()?;
 
         Ok(Self {
-            connection,
             args: args.to_owned(),
-            client_name: client_name.to_owned(),
+            binding: binding.to_owned(),
         })
     }
 }
 
 impl RabbitMQ {
-    async fn create_channel(&self, exchange: &str, queue: &str) -> eyre::Result<Channel> {
-        let channel = self.connection.open_channel(None).await?;
+    async fn create_channel(&self, connection: &Connection, exchange: &str) -> Result<Channel> {
+        let channel = connection.open_channel(None).await?;
         channel
             .exchange_declare(ExchangeDeclareArguments::new(exchange, "topic"))
             .await?;
+        Ok(channel)
+    }
+
+    /// Declare the job queue and bind it to every table the job consumes
+    async fn create_consumer_channel(
+        &self,
+        connection: &Connection,
+        exchange: &str,
+    ) -> Result<Channel> {
+        let channel = self.create_channel(connection, exchange).await?;
+        let queue = &self.binding.queue;
         channel
             .queue_declare(QueueDeclareArguments::new(queue))
             .await?;
-        channel
-            .queue_bind(QueueBindArguments::new(queue, exchange, queue))
-            .await?;
+        for routing_key in &self.binding.routing_keys {
+            channel
+                .queue_bind(QueueBindArguments::new(queue, exchange, routing_key))
+                .await?;
+        }
         Ok(channel)
     }
-}
 
-#[async_trait]
-impl MessageQueueTrait for RabbitMQ {
-    async fn run(
+    /// Open the connection, declare the queue & subscribe the consumer, open the publishing
+    /// channel in confirm mode
+    async fn connect(
         &self,
-        source_sender: AsyncSender<Message>,
-        sink_receiver: AsyncReceiver<Message>,
-    ) -> Result<()> {
-        // Consuming message
+        source_sender: &AsyncSender<Incoming>,
+        shutdown: &Shutdown,
+    ) -> Result<Session> {
+        let connection = Connection::open(&self.args.connection_arguments()?).await?;
+        connection
+            .register_callback(DefaultConnectionCallback)
+            .await?;
 
-        let task_consume = || async move {
-            let channel = self
-                .create_channel(&self.args.exchange, &self.args.source_queue)
-                .await
-                .unwrap();
+        let exchange = &self.args.exchange;
+        let consumer = self.create_consumer_channel(&connection, exchange).await?;
+        // NOTE: the consumer waits while the job channel is full, the prefetch bounds
+        // the deliveries buffered meanwhile
+        consumer
+            .basic_qos(BasicQosArguments::new(0, self.args.prefetch, false))
+            .await?;
+        let acks = Acknowledgements::default();
+        let consumer_tag = match shutdown.is_requested() {
+            // NOTE: reconnecting to publish the last output messages, nothing is consumed
+            true => None,
+            false => {
+                let consumer_name = format!("{}-consumer", self.binding.job_id);
+                let consumer_tag = consumer
+                    .basic_consume(
+                        RabbitMqConsumer {
+                            sender: source_sender.clone(),
+                            acks: acks.clone(),
+                        },
+                        BasicConsumeArguments::new(&self.binding.queue, &consumer_name),
+                    )
+                    .await?;
+                Some(consumer_tag)
+            }
+        };
 
-            let consumer_name = format!("{}-consumer", self.client_name);
+        let publisher = self.create_channel(&connection, exchange).await?;
+        let (sender, confirms) = kanal::unbounded_async();
+        publisher
+            .register_callback(ConfirmCallback { sender })
+            .await?;
+        publisher
+            .confirm_select(ConfirmSelectArguments::new(false))
+            .await?;
 
-            // FIXME: dynamic consumer name
-            channel
-                .basic_consume(
-                    RabbitMqConsumer {
-                        sender: source_sender.clone(),
-                    },
-                    BasicConsumeArguments::new(&self.args.source_queue, &consumer_name),
+        Ok(Session {
+            connection,
+            consumer,
+            consumer_tag,
+            acks,
+            publisher,
+            confirms,
+            delivery_tag: 0,
+        })
+    }
+
+    /// Publish a message and wait for the broker to confirm it, retry when it is nacked or
+    /// not confirmed in time
+    async fn publish(&self, session: &mut Session, msg: &Message) -> Result<()> {
+        let message = serde_json::to_string(msg)?;
+        // NOTE: messages are routed by table name & chain, see common::topology
+        let routing_key = msg.routing_key();
+
+        for attempt in 1..=self.args.publish_retries.max(1) {
+            let publish_args = BasicPublishArguments::new(&self.args.exchange, &routing_key);
+            session
+                .publisher
+                .basic_publish(
+                    BasicProperties::default(),
+                    message.as_bytes().to_vec(),
+                    publish_args,
                 )
-                .await
-                .expect("Failed to start consuming messages");
+                .await?;
+            session.delivery_tag += 1;
 
-            tokio::task::spawn(async move {
-                loop {
-                    tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+            match self.wait_for_confirm(session).await {
+                Ok(true) => return Ok(()),
+                Ok(false) => log::warn!("RabbitMQ nacked message, attempt {}: {}", attempt, msg),
+                Err(error) => log::warn!(
+                    "RabbitMQ did not confirm message, attempt {}: {}",
+                    attempt,
+                    error
+                ),
+            }
+        }
+
+        eyre::bail!("Failed to publish message: {}", msg)
+    }
+
+    /// Wait for the confirm of the last delivery tag, return whether it was acked
+    async fn wait_for_confirm(&self, session: &Session) -> Result<bool> {
+        let wait = async {
+            loop {
+                let confirm = session.confirms.recv().await?;
+                // NOTE: a confirm may cover several tags, earlier ones were already confirmed
+                if confirm.delivery_tag >= session.delivery_tag {
+                    return Ok::<_, eyre::Report>(confirm.acked);
                 }
-            })
-            .await
-            .expect("Task failed");
+            }
         };
+        tokio::time::timeout(CONFIRM_TIMEOUT, wait)
+            .await
+            .map_err(|_| eyre::eyre!("Confirm timed out after {:?}", CONFIRM_TIMEOUT))?
+    }
 
-        // Publishing message
-        let task_publish = || async move {
-            let channel = self
-                .create_channel(&self.args.exchange, &self.args.sink_queue)
-                .await
-                .unwrap();
-            let publish_args =
-                BasicPublishArguments::new(&self.args.exchange, &self.args.sink_queue);
-
-            while let Ok(msg) = sink_receiver.recv().await {
-                let message = serde_json::to_string(&msg).unwrap();
-                channel
-                    .basic_publish(
-                        BasicProperties::default(),
-                        message.as_bytes().to_vec(),
-                        publish_args.clone(),
-                    )
-                    .await
-                    .expect("Failed to publish message");
+    /// Publish the output messages until the session fails or the sink channel is disconnected.
+    /// The message being published when it fails is kept in `pending`, to be published again
+    /// on the next session
+    async fn serve(
+        &self,
+        session: &mut Session,
+        sink_receiver: &AsyncReceiver<Outgoing>,
+        pending: &mut Option<Outgoing>,
+        shutdown: &Shutdown,
+        health: &JobHealth,
+    ) -> Result<()> {
+        loop {
+            let outgoing = match pending.take() {
+                Some(outgoing) => outgoing,
+                None => select! {
+                    biased;
+                    _ = shutdown.requested(), if session.consumer_tag.is_some() => {
+                        Self::stop_consuming(session).await?;
+                        health.set_queue_status(QueueStatus::Connected);
+                        continue;
+                    },
+                    outgoing = sink_receiver.recv() => match outgoing {
+                        Ok(outgoing) => outgoing,
+                        Err(_) => return Ok(()),
+                    },
+                },
+            };
+            if let Err(error) = self.publish(session, &outgoing.message).await {
+                *pending = Some(outgoing);
+                return Err(error);
+            }
+            outgoing.acknowledge().await;
+        }
+    }
+
+    /// Cancel the consumer, the deliveries in flight are still acknowledged or rejected
+    async fn stop_consuming(session: &mut Session) -> Result<()> {
+        if let Some(consumer_tag) = session.consumer_tag.take() {
+            session
+                .consumer
+                .basic_cancel(BasicCancelArguments::new(&consumer_tag))
+                .await?;
+            log::info!("Stopped consuming, consumer: {}", consumer_tag);
+        }
+        Ok(())
+    }
+
+    /// Wait for the deliveries to be acknowledged or rejected and close the connection
+    async fn close(session: Session) -> Result<()> {
+        let mut acks = std::mem::take(&mut *session.acks.lock().unwrap());
+        while acks.join_next().await.is_some() {}
+        session.connection.close().await?;
+        Ok(())
+    }
+
+    /// Resolve once the connection or one of the channels is closed
+    async fn watch(connection: Connection, channels: Vec<Channel>) -> Result<()> {
+        let closed = async {
+            loop {
+                tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+                if !connection.is_open() || channels.iter().any(|channel| !channel.is_open()) {
+                    return;
+                }
             }
         };
 
         select! {
-            _ = task_consume() => {},
-            _ = task_publish() => {},
+            _ = connection.listen_network_io_failure() => eyre::bail!("RabbitMQ network failure"),
+            _ = closed => eyre::bail!("RabbitMQ connection or channel closed"),
         }
+    }
+}
+
+#[async_trait]
+impl MessageQueueTrait for RabbitMQ {
+    async fn run(
+        &self,
+        source_sender: AsyncSender<Incoming>,
+        sink_receiver: AsyncReceiver<Outgoing>,
+        shutdown: Shutdown,
+        health: JobHealth,
+    ) -> Result<()> {
+        let backoff = || {
+            ExponentialBackoff::from_millis(2)
+                .factor(500)
+                .max_delay(Duration::from_secs(self.args.max_reconnect_delay))
+        };
+        let mut delays = backoff();
+        let mut pending = None;
 
-        eyre::bail!("RabbitMQ exited unexpectedly")
+        loop {
+            let error = match self.connect(&source_sender, &shutdown).await {
+                Ok(mut session) => {
+                    log::info!("Connected to RabbitMQ: queue={}", self.binding.queue);
+                    delays = backoff();
+                    health.set_queue_status(match session.consumer_tag {
+                        Some(_) => QueueStatus::Consuming,
+                        None => QueueStatus::Connected,
+                    });
+                    let watched = vec![session.consumer.clone(), session.publisher.clone()];
+                    let result = select! {
+                        result = Self::watch(session.connection.clone(), watched) => result,
+                        result = self.serve(&mut session, &sink_receiver, &mut pending, &shutdown, &health) => result,
+                    };
+                    health.set_queue_status(QueueStatus::Disconnected);
+                    match result {
+                        // NOTE: the outbox relay stops last, once the job processed its messages
+                        Ok(()) if shutdown.is_requested() => {
+                            Self::close(session).await?;
+                            log::info!("Closed RabbitMQ connection: queue={}", self.binding.queue);
+                            return Ok(());
+                        }
+                        Ok(()) => eyre::bail!("RabbitMQ exited unexpectedly"),
+                        Err(error) => {
+                            session.connection.close().await.ok();
+                            error
+                        }
+                    }
+                }
+                Err(error) => error,
+            };
+
+            let delay = delays.next().unwrap_or(Duration::from_secs(1));
+            log::error!(
+                "RabbitMQ connection lost, reconnecting in {:?}: {}",
+                delay,
+                error
+            );
+            tokio::time::sleep(delay).await;
+        }
     }
 }
@@ -0,0 +1,59 @@
+use crate::mq::Outgoing;
+use crate::shutdown::Shutdown;
+use common::ETLTrait;
+use kanal::AsyncSender;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Largest number of outbox messages loaded at once
+const BATCH_SIZE: i64 = 100;
+
+/// Publish the messages queued in the outbox of a job, in order, and mark them as sent once the
+/// queue acknowledged them. Messages of jobs completed before a crash are published on restart,
+/// a message published right before a crash may be published twice
+pub struct OutboxRelay {
+    etl: Arc<dyn ETLTrait>,
+    poll_interval: Duration,
+}
+
+impl OutboxRelay {
+    pub fn new(etl: Arc<dyn ETLTrait>, poll_interval: Duration) -> Self {
+        Self { etl, poll_interval }
+    }
+
+    /// Publish the outbox until the job stopped processing and its last messages are published,
+    /// then disconnect from the queue
+    pub async fn run(
+        &self,
+        publisher: AsyncSender<Outgoing>,
+        processing_stopped: Shutdown,
+    ) -> eyre::Result<()> {
+        let job_manager = self.etl.job_manager();
+        loop {
+            let messages = job_manager.unsent_messages(BATCH_SIZE)?;
+            if messages.is_empty() {
+                if processing_stopped.is_requested() {
+                    log::info!("Outbox of job {} is published", job_manager.job_id());
+                    return Ok(());
+                }
+                // NOTE: completed jobs wake the relay up, the poll picks up anything else
+                tokio::select! {
+                    _ = job_manager.outbox_written() => {},
+                    _ = tokio::time::sleep(self.poll_interval) => {},
+                    _ = processing_stopped.requested() => {},
+                }
+                continue;
+            }
+
+            for (outbox_id, msg) in messages {
+                log::info!("Publishing message {} of the outbox: {}", outbox_id, msg);
+                let (outgoing, published) = Outgoing::new(msg);
+                publisher.send(outgoing).await?;
+                published.recv().await.map_err(|_| {
+                    eyre::eyre!("Message {} of the outbox was not published", outbox_id)
+                })?;
+                job_manager.mark_message_as_sent(outbox_id)?;
+            }
+        }
+    }
+}
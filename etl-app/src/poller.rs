@@ -0,0 +1,238 @@
+use crate::mq::Incoming;
+use crate::shutdown::Shutdown;
+use common::messages::Message;
+use common::ETLTrait;
+use database::partition_bounds;
+use database::Connection;
+use database::PgConnection;
+use database::Range;
+use database::RangeQuery;
+use database::Table;
+use kanal::AsyncSender;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Source table polled for new rows, for tables populated without an upstream message
+/*
+"poll": { "table": "actions", "column": "block_number", "interval_secs": 10, "chunk_size": 10000 }
+*/
+#[derive(Debug, Deserialize, Clone)]
+pub struct PollConfig {
+    /// Consumed table to poll, eg: actions
+    pub table: Table,
+    /// Name of the table in the source database, default to the table name
+    pub sql_table: Option<String>,
+    /// BIGINT column the ranges are made of, compared with the watermark of the job
+    #[serde(default = "PollConfig::default_column")]
+    pub column: String,
+    /// Poll & checkpoint every `chain_id` separately, the ranges are filtered on their chain
+    #[serde(default = "PollConfig::default_per_chain")]
+    pub per_chain: bool,
+    #[serde(default = "PollConfig::default_interval_secs")]
+    pub interval_secs: u64,
+    /// Largest range of a synthesized message
+    #[serde(default = "PollConfig::default_chunk_size")]
+    pub chunk_size: i64,
+}
+
+impl PollConfig {
+    fn default_column() -> String {
+        "block_number".to_string()
+    }
+
+    fn default_per_chain() -> bool {
+        true
+    }
+
+    fn default_interval_secs() -> u64 {
+        10
+    }
+
+    fn default_chunk_size() -> i64 {
+        10000
+    }
+}
+
+/// Query the highest value of the polled column per chain and send `DataStoreUpdated` messages
+/// for the values past the checkpoint to the job, as if they came from the queue
+pub struct Poller {
+    etl: Arc<dyn ETLTrait>,
+    source: PgConnection,
+    config: PollConfig,
+    sender: AsyncSender<Incoming>,
+    shutdown: Shutdown,
+    /// Highest value sent per chain: the watermark only moves once the range is processed
+    sent: HashMap<Option<i64>, i64>,
+}
+
+impl Poller {
+    pub fn new(
+        etl: Arc<dyn ETLTrait>,
+        source: &str,
+        config: PollConfig,
+        sender: AsyncSender<Incoming>,
+        shutdown: Shutdown,
+    ) -> eyre::Result<Self> {
+        if !etl.consumed_tables().contains(&config.table) {
+            eyre::bail!(
+                "Polled table {} is not consumed by job {}",
+                config.table.name(),
+                etl.job_manager().job_id()
+            );
+        }
+        // NOTE: the checkpoint of a chain is its watermark
+        if !etl.job_manager().tracks_watermark(&config.table) {
+            eyre::bail!(
+                "Polled table {} has no watermark in job {}, add it to its `watermarks`",
+                config.table.name(),
+                etl.job_manager().job_id()
+            );
+        }
+        if config.chunk_size <= 0 {
+            eyre::bail!("Poll chunk_size must be positive");
+        }
+        // NOTE: the ranges are synthesized, check the table accepts their filters up front
+        let sample = RangeQuery {
+            range: Range::Numeric { from: 0, to: 0 },
+            filters: Self::filters(config.per_chain.then_some(0)),
+        };
+        config.table.validate(&sample).map_err(|error| {
+            eyre::eyre!(
+                "Polled ranges of {} with per_chain: {} are invalid: {}",
+                config.table.name(),
+                config.per_chain,
+                error
+            )
+        })?;
+
+        let source = PgConnection::establish(source)
+            .map_err(|error| eyre::eyre!("Failed to connect to the polled source: {}", error))?;
+        Ok(Self {
+            etl,
+            source,
+            config,
+            sender,
+            shutdown,
+            sent: HashMap::new(),
+        })
+    }
+
+    pub async fn run(mut self) -> eyre::Result<()> {
+        log::info!(
+            "Polling {}.{} every {}s for job {}",
+            self.config.table.name(),
+            self.config.column,
+            self.config.interval_secs,
+            self.etl.job_manager().job_id()
+        );
+        while !self.shutdown.is_requested() {
+            // NOTE: the source may be unavailable for a while, retry on the next poll
+            match self.poll() {
+                Ok(messages) => {
+                    for msg in messages {
+                        log::info!("Polled new rows: {}", msg);
+                        tokio::select! {
+                            result = self.sender.send(Incoming::new(msg)) => match result {
+                                Ok(()) => {}
+                                // NOTE: the job may stop on shutdown before this task sees it
+                                Err(_) if self.shutdown.is_requested() => return Ok(()),
+                                Err(error) => return Err(error.into()),
+                            },
+                            // NOTE: the next poll after a restart sends the rest again
+                            _ = self.shutdown.requested() => return Ok(()),
+                        }
+                    }
+                }
+                Err(error) => {
+                    log::error!("Polling {} failed: {:?}", self.config.table.name(), error)
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(self.config.interval_secs)) => {},
+                _ = self.shutdown.requested() => {},
+            }
+        }
+        Ok(())
+    }
+
+    /// Filters of the ranges polled for a chain, or for the whole table
+    fn filters(partition: Option<i64>) -> serde_json::Value {
+        match partition {
+            Some(chain_id) => serde_json::json!({ "chain_id": chain_id }),
+            None => serde_json::Value::Null,
+        }
+    }
+
+    /// Highest value processed or sent for the chain
+    fn checkpoint(&self, chain_id: Option<i64>) -> eyre::Result<Option<i64>> {
+        let processed = match self
+            .etl
+            .job_manager()
+            .watermark(&self.config.table, chain_id)?
+        {
+            Some(watermark) => match watermark.range()? {
+                Range::Numeric { to, .. } => Some(to),
+                other => eyre::bail!("Watermark {:?} is not a numeric range", other),
+            },
+            None => None,
+        };
+        Ok(processed.max(self.sent.get(&chain_id).copied()))
+    }
+
+    /// Messages of the rows added since the checkpoint of every chain of the job shard,
+    /// from the lowest value of the chain when nothing was processed yet
+    fn poll(&mut self) -> eyre::Result<Vec<Message>> {
+        let sql_table = self
+            .config
+            .sql_table
+            .clone()
+            .unwrap_or(self.config.table.name());
+        let partition = self.config.per_chain.then_some("chain_id");
+        let bounds =
+            partition_bounds(&mut self.source, &sql_table, &self.config.column, partition)?;
+
+        let mut messages = vec![];
+        for bounds in bounds {
+            if !self.etl.job_manager().shard().contains(bounds.partition) {
+                continue;
+            }
+
+            let from = match self.checkpoint(bounds.partition)? {
+                Some(checkpoint) if checkpoint >= bounds.max => continue,
+                Some(checkpoint) => checkpoint + 1,
+                None => bounds.min,
+            };
+            let range = Range::Numeric {
+                from,
+                to: bounds.max,
+            };
+            let filters = Self::filters(bounds.partition);
+            let chunks = range.chunk_count(self.config.chunk_size).ok_or_else(|| {
+                eyre::eyre!(
+                    "Range {:?} is too wide to split into chunks of {}",
+                    range,
+                    self.config.chunk_size
+                )
+            })?;
+            for index in 0..chunks {
+                let chunk = range
+                    .chunk(self.config.chunk_size, index)
+                    .ok_or_else(|| eyre::eyre!("Chunk {} out of range", index))?;
+                let msg = Message::DataStoreUpdated {
+                    table: self.config.table.clone(),
+                    range: RangeQuery {
+                        range: chunk,
+                        filters: filters.clone(),
+                    },
+                };
+                self.etl.validate_message(&msg)?;
+                messages.push(msg);
+            }
+            self.sent.insert(bounds.partition, bounds.max);
+        }
+        Ok(messages)
+    }
+}
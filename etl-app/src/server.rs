@@ -1,23 +1,202 @@
+use crate::backfill::backfill_report;
+use crate::backfill::BackfillRequest;
+use crate::health::JobHealth;
+use crate::health::QueueStatus;
+use crate::mq::Incoming;
+use crate::shutdown::Shutdown;
+use clap::Parser;
 use common::messages::Message;
+use common::ETLTrait;
+use database::BackfillStatus;
+use database::EtlJobStatus;
+use eyre::WrapErr;
 use kanal::AsyncSender;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::convert::Infallible;
+#[cfg(feature = "api_tls")]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use warp::http::header;
 use warp::http::StatusCode;
-use warp::{reply, Filter};
+use warp::hyper::body::Bytes;
+use warp::reply::Response;
+use warp::Rejection;
+use warp::{reply, Filter, Reply};
+
+/// Largest body accepted by `/process`, messages are a few hundred bytes
+const MAX_REQUEST_BYTES: u64 = 64 * 1024;
+
+/// Largest body accepted by `/process/batch`
+const MAX_BATCH_BYTES: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug, Parser, Clone)]
+#[command(author, version, about, long_about = None)]
+pub struct ApiArgs {
+    /// Bearer token required by the routes changing state: `/process`, `/process/batch` & the backfills.
+    /// Open to anyone reaching the port when unset
+    #[arg(long = "api-token", env = "ETL_API_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+    /// PEM certificate chain served over TLS
+    #[cfg(feature = "api_tls")]
+    #[arg(long = "api-tls-cert", env = "ETL_API_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key of the certificate
+    #[cfg(feature = "api_tls")]
+    #[arg(long = "api-tls-key", env = "ETL_API_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+    /// PEM file of the CA signing the client certificates, every connection must present one
+    #[cfg(feature = "api_tls")]
+    #[arg(
+        long = "api-client-ca",
+        env = "ETL_API_CLIENT_CA",
+        requires = "tls_cert"
+    )]
+    pub client_ca: Option<PathBuf>,
+}
+
+/// Request without the bearer token of `ApiArgs::token`
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+/// A job running in this process, as seen by the admin API
+#[derive(Clone)]
+pub struct JobHandle {
+    pub sender: AsyncSender<Incoming>,
+    pub etl: Arc<dyn ETLTrait>,
+    pub health: JobHealth,
+}
+
+type Jobs = HashMap<String, JobHandle>;
 
 pub struct Server {
     port: u16,
+    dry_run: bool,
+    /// Time a job may spend on a message while others wait for it
+    stall_timeout: Duration,
+    api: ApiArgs,
+}
+
+#[derive(Debug, Deserialize)]
+struct JobQuery {
+    /// Target job, can be omitted when only one job is running
+    job: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProcessQuery {
+    job: Option<String>,
+    /// Reply with what the message would write & emit instead of processing it
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ThrottleRequest {
+    throttle_ms: i64,
+}
+
+/// Messages of a batch: a JSON array, or one JSON message per line (NDJSON).
+/// Every item is parsed on its own, so one invalid item does not hide the others
+fn parse_batch(body: &[u8]) -> Result<Vec<Result<Message, String>>, String> {
+    let parse = |value| serde_json::from_value::<Message>(value).map_err(|error| error.to_string());
+    let body = std::str::from_utf8(body).map_err(|error| format!("Invalid batch: {}", error))?;
+
+    let items: Vec<_> = match body.trim_start().starts_with('[') {
+        true => serde_json::from_str::<Vec<serde_json::Value>>(body)
+            .map_err(|error| format!("Invalid batch: {}", error))?
+            .into_iter()
+            .map(parse)
+            .collect(),
+        false => body
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|error| error.to_string())
+                    .and_then(parse)
+            })
+            .collect(),
+    };
+    if items.is_empty() {
+        return Err("Empty batch".to_string());
+    }
+    Ok(items)
+}
+
+/// Saved request as reported by the admin API
+fn request_report(job: &EtlJobStatus) -> serde_json::Value {
+    let mut report = serde_json::to_value(job).unwrap_or_default();
+    report["finished"] = serde_json::json!(job.finished_at.is_some());
+    report
+}
+
+/// Compare secrets without leaking the position of the first difference through timing
+fn constant_time_eq(expected: &[u8], given: &[u8]) -> bool {
+    expected.len() == given.len()
+        && expected
+            .iter()
+            .zip(given)
+            .fold(0, |diff, (expected, given)| diff | (expected ^ given))
+            == 0
+}
+
+fn error_reply(message: String, status: StatusCode) -> Response {
+    reply::with_status(
+        reply::json(&serde_json::json!({ "error": message })),
+        status,
+    )
+    .into_response()
 }
 
 impl Server {
-    pub fn new(port: u16) -> Self {
-        Self { port }
+    pub fn new(port: u16, dry_run: bool, stall_timeout: Duration, api: ApiArgs) -> Self {
+        Self {
+            port,
+            dry_run,
+            stall_timeout,
+            api,
+        }
+    }
+
+    fn with_jobs(jobs: Jobs) -> impl Filter<Extract = (Jobs,), Error = Infallible> + Clone {
+        warp::any().map(move || jobs.clone())
     }
 
-    #[allow(dead_code)]
-    fn with_sender<T>(
-        query_sender: AsyncSender<T>,
-    ) -> impl Filter<Extract = (AsyncSender<T>,), Error = Infallible> + Clone {
-        warp::any().map(move || query_sender.clone())
+    /// Reject requests without the bearer token, when one is configured
+    fn authorized(token: Option<String>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        warp::header::optional::<String>(header::AUTHORIZATION.as_str())
+            .and_then(move |authorization: Option<String>| {
+                let token = token.clone();
+                async move {
+                    let Some(token) = token else {
+                        return Ok(());
+                    };
+                    let given = authorization
+                        .as_deref()
+                        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+                        .unwrap_or_default();
+                    match constant_time_eq(token.as_bytes(), given.as_bytes()) {
+                        true => Ok(()),
+                        false => Err(warp::reject::custom(Unauthorized)),
+                    }
+                }
+            })
+            .untuple_one()
+    }
+
+    async fn unauthorized_reply(rejection: Rejection) -> Result<Response, Rejection> {
+        if rejection.find::<Unauthorized>().is_none() {
+            return Err(rejection);
+        }
+        let response = error_reply(
+            "Missing or invalid bearer token".to_string(),
+            StatusCode::UNAUTHORIZED,
+        );
+        Ok(reply::with_header(response, header::WWW_AUTHENTICATE, "Bearer").into_response())
     }
 
     async fn setup(&self) -> eyre::Result<()> {
@@ -25,35 +204,585 @@ impl Server {
         Ok(())
     }
 
+    fn find_job(jobs: &Jobs, job: &Option<String>) -> Result<JobHandle, Response> {
+        let handle = match job {
+            Some(job) => jobs.get(job),
+            None if jobs.len() == 1 => jobs.values().next(),
+            None => {
+                return Err(error_reply(
+                    "Multiple jobs are running, specify one with ?job=".to_string(),
+                    StatusCode::BAD_REQUEST,
+                ))
+            }
+        };
+
+        handle.cloned().ok_or_else(|| {
+            error_reply(
+                format!("Unknown job: {}", job.clone().unwrap_or_default()),
+                StatusCode::NOT_FOUND,
+            )
+        })
+    }
+
+    /// `503` until the job resumed the requests left unfinished by the last stop: a request saved
+    /// meanwhile could be resumed and queued, and processed twice
+    fn check_resumed(job: &JobHandle) -> Result<(), Response> {
+        if job.health.is_resumed() {
+            return Ok(());
+        }
+        Err(error_reply(
+            "Job is resuming its unfinished requests, retry later".to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ))
+    }
+
+    /// Reply with `report` of every job, `503` when one of them failed `check`
+    fn probe_reply(
+        jobs: &Jobs,
+        check: impl Fn(&JobHandle) -> (bool, serde_json::Value),
+    ) -> Response {
+        let mut passed = true;
+        let mut reports = serde_json::Map::new();
+        for (scope, job) in jobs {
+            let (ok, report) = check(job);
+            passed &= ok;
+            reports.insert(scope.clone(), report);
+        }
+
+        let status = match passed {
+            true => StatusCode::OK,
+            false => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        reply::with_status(reply::json(&reports), status).into_response()
+    }
+
+    /// Ready once every job resumed its unfinished requests, consumes its queue and reaches
+    /// its databases
+    async fn readiness(jobs: Jobs) -> Result<Response, Infallible> {
+        Ok(Self::probe_reply(&jobs, |job| {
+            let errors: Vec<_> = [
+                job.etl.check_connections(),
+                job.etl
+                    .job_manager()
+                    .check_connection()
+                    .wrap_err("Job-manager database"),
+            ]
+            .into_iter()
+            .filter_map(Result::err)
+            .map(|error| format!("{:#}", error))
+            .collect();
+            let queue = job.health.queue_status();
+            let resumed = job.health.is_resumed();
+
+            let ready = errors.is_empty()
+                && resumed
+                && matches!(queue, QueueStatus::Consuming | QueueStatus::Disabled);
+            let report = serde_json::json!({
+                "ready": ready,
+                "resumed": resumed,
+                "queue": queue,
+                "database_errors": errors,
+            });
+            (ready, report)
+        }))
+    }
+
+    /// Alive unless a job is stuck on a message while others wait for it
+    async fn liveness(jobs: Jobs, stall_timeout: Duration) -> Result<Response, Infallible> {
+        Ok(Self::probe_reply(&jobs, |job| {
+            let backlog = job.sender.len();
+            let stalled_for = job.health.stalled_for(backlog);
+            let alive = stalled_for.map_or(true, |stalled_for| stalled_for < stall_timeout);
+            let report = serde_json::json!({
+                "alive": alive,
+                "backlog": backlog,
+                "stalled_for_secs": stalled_for.map(|stalled_for| stalled_for.as_secs()),
+            });
+            (alive, report)
+        }))
+    }
+
     async fn request_processing(
-        request_message: Message,
-        sender: AsyncSender<Message>,
-    ) -> Result<impl warp::Reply, Infallible> {
-        sender.send(request_message).await.expect("Failed to send");
-        Ok(reply::with_status("OK", StatusCode::OK))
+        query: ProcessQuery,
+        body: Bytes,
+        jobs: Jobs,
+        dry_run: bool,
+    ) -> Result<Response, Infallible> {
+        let request_message = match serde_json::from_slice::<Message>(&body) {
+            Ok(msg) => msg,
+            Err(error) => {
+                return Ok(error_reply(
+                    format!("Invalid message: {}", error),
+                    StatusCode::BAD_REQUEST,
+                ))
+            }
+        };
+        let job = match Self::find_job(&jobs, &query.job) {
+            Ok(job) => job,
+            Err(response) => return Ok(response),
+        };
+        if let Err(error) = job.etl.validate_message(&request_message) {
+            return Ok(error_reply(
+                error.to_string(),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ));
+        }
+
+        if dry_run || query.dry_run {
+            return match job.etl.dry_run(request_message).await {
+                Ok(report) => Ok(reply::json(&report).into_response()),
+                Err(error) => Ok(error_reply(
+                    format!("{:?}", error),
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                )),
+            };
+        }
+
+        if let Err(response) = Self::check_resumed(&job) {
+            return Ok(response);
+        }
+        // NOTE: saved first to reply with its ID, a request not processed before a shutdown
+        // is resumed on restart like any unfinished job
+        let saved = match job.etl.job_manager().save(&request_message) {
+            Ok(saved) => saved,
+            Err(error) => {
+                return Ok(error_reply(
+                    error.to_string(),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ))
+            }
+        };
+        let incoming = Incoming::saved(request_message, saved.id);
+        if job.sender.send(incoming).await.is_err() {
+            log::warn!(
+                "Job is shutting down, request {} is resumed on restart",
+                saved.id
+            );
+        }
+        Ok(
+            reply::with_status(reply::json(&request_report(&saved)), StatusCode::ACCEPTED)
+                .into_response(),
+        )
     }
 
-    pub async fn run(&self, message_sender: AsyncSender<Message>) -> eyre::Result<()> {
+    /// Validate all the messages of a batch, then save them in one transaction, or none of them
+    /// when one is invalid, and queue them in order
+    async fn request_batch_processing(
+        query: ProcessQuery,
+        body: Bytes,
+        jobs: Jobs,
+        dry_run: bool,
+    ) -> Result<Response, Infallible> {
+        let items = match parse_batch(&body) {
+            Ok(items) => items,
+            Err(error) => return Ok(error_reply(error, StatusCode::BAD_REQUEST)),
+        };
+        let job = match Self::find_job(&jobs, &query.job) {
+            Ok(job) => job,
+            Err(response) => return Ok(response),
+        };
+
+        let mut messages = vec![];
+        let mut errors = vec![];
+        for (index, item) in items.into_iter().enumerate() {
+            let checked = item.and_then(|msg| {
+                job.etl
+                    .validate_message(&msg)
+                    .map_err(|error| error.to_string())?;
+                Ok(msg)
+            });
+            match checked {
+                Ok(msg) => messages.push(msg),
+                Err(error) => errors.push(serde_json::json!({ "index": index, "error": error })),
+            }
+        }
+        if !errors.is_empty() {
+            let report = serde_json::json!({
+                "error": format!(
+                    "{} of {} messages are invalid, none was saved",
+                    errors.len(),
+                    errors.len() + messages.len()
+                ),
+                "items": errors,
+            });
+            return Ok(
+                reply::with_status(reply::json(&report), StatusCode::UNPROCESSABLE_ENTITY)
+                    .into_response(),
+            );
+        }
+
+        if dry_run || query.dry_run {
+            let mut reports = vec![];
+            for msg in messages {
+                match job.etl.dry_run(msg).await {
+                    Ok(report) => reports.push(report),
+                    Err(error) => {
+                        return Ok(error_reply(
+                            format!("Message {}: {:?}", reports.len(), error),
+                            StatusCode::UNPROCESSABLE_ENTITY,
+                        ))
+                    }
+                }
+            }
+            return Ok(reply::json(&serde_json::json!({ "reports": reports })).into_response());
+        }
+
+        if let Err(response) = Self::check_resumed(&job) {
+            return Ok(response);
+        }
+        let saved = match job.etl.job_manager().save_all(&messages) {
+            Ok(saved) => saved,
+            Err(error) => {
+                return Ok(error_reply(
+                    error.to_string(),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ))
+            }
+        };
+        let requests: Vec<_> = saved.iter().map(request_report).collect();
+
+        // NOTE: queued in the background, the batch may not fit in the job channel.
+        // Requests not queued before a shutdown are resumed on restart
+        let sender = job.sender.clone();
+        let job_pks = saved.iter().map(|saved| saved.id).collect::<Vec<_>>();
+        tokio::spawn(async move {
+            for (msg, job_pk) in messages.into_iter().zip(job_pks) {
+                if sender.send(Incoming::saved(msg, job_pk)).await.is_err() {
+                    log::warn!(
+                        "Job is shutting down, batch requests from {} are resumed on restart",
+                        job_pk
+                    );
+                    return;
+                }
+            }
+        });
+
+        let report = serde_json::json!({ "requests": requests });
+        Ok(reply::with_status(reply::json(&report), StatusCode::ACCEPTED).into_response())
+    }
+
+    /// State of a request sent to `/process`
+    async fn get_request(job_pk: i64, query: JobQuery, jobs: Jobs) -> Result<Response, Infallible> {
+        let job = match Self::find_job(&jobs, &query.job) {
+            Ok(job) => job,
+            Err(response) => return Ok(response),
+        };
+
+        match job.etl.job_manager().find(job_pk) {
+            Ok(Some(saved)) => Ok(reply::json(&request_report(&saved)).into_response()),
+            Ok(None) => Ok(error_reply(
+                format!("Unknown request: {}", job_pk),
+                StatusCode::NOT_FOUND,
+            )),
+            Err(error) => Ok(error_reply(
+                error.to_string(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )),
+        }
+    }
+
+    async fn create_backfill(
+        query: JobQuery,
+        request: BackfillRequest,
+        jobs: Jobs,
+    ) -> Result<Response, Infallible> {
+        let job = match Self::find_job(&jobs, &query.job) {
+            Ok(job) => job,
+            Err(response) => return Ok(response),
+        };
+
+        match request.submit(job.etl.as_ref()) {
+            Ok(backfill) => Ok(reply::json(&backfill_report(&backfill)).into_response()),
+            Err(error) => Ok(error_reply(error.to_string(), StatusCode::BAD_REQUEST)),
+        }
+    }
+
+    async fn list_backfills(query: JobQuery, jobs: Jobs) -> Result<Response, Infallible> {
+        let job = match Self::find_job(&jobs, &query.job) {
+            Ok(job) => job,
+            Err(response) => return Ok(response),
+        };
+
+        match job.etl.job_manager().backfills() {
+            Ok(backfills) => {
+                let reports: Vec<_> = backfills.iter().map(backfill_report).collect();
+                Ok(reply::json(&reports).into_response())
+            }
+            Err(error) => Ok(error_reply(
+                error.to_string(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )),
+        }
+    }
+
+    /// Processed range & gaps of every source table partition of the job
+    async fn list_watermarks(query: JobQuery, jobs: Jobs) -> Result<Response, Infallible> {
+        let job = match Self::find_job(&jobs, &query.job) {
+            Ok(job) => job,
+            Err(response) => return Ok(response),
+        };
+
+        match job.etl.job_manager().watermarks() {
+            Ok(watermarks) => Ok(reply::json(&watermarks).into_response()),
+            Err(error) => Ok(error_reply(
+                error.to_string(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )),
+        }
+    }
+
+    /// Apply `action` to a backfill of the job then reply with its latest state
+    fn update_backfill(
+        job: &JobHandle,
+        backfill_id: i64,
+        action: impl FnOnce() -> eyre::Result<()>,
+    ) -> Response {
+        let job_manager = job.etl.job_manager();
+        match job_manager.backfill(backfill_id) {
+            Ok(Some(_)) => (),
+            Ok(None) => {
+                return error_reply(
+                    format!("Unknown backfill: {}", backfill_id),
+                    StatusCode::NOT_FOUND,
+                )
+            }
+            Err(error) => return error_reply(error.to_string(), StatusCode::INTERNAL_SERVER_ERROR),
+        };
+
+        let result = action().and_then(|_| job_manager.backfill(backfill_id));
+        match result {
+            Ok(Some(backfill)) => reply::json(&backfill_report(&backfill)).into_response(),
+            Ok(None) => error_reply(
+                format!("Unknown backfill: {}", backfill_id),
+                StatusCode::NOT_FOUND,
+            ),
+            Err(error) => error_reply(error.to_string(), StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    async fn get_backfill(
+        backfill_id: i64,
+        query: JobQuery,
+        jobs: Jobs,
+    ) -> Result<Response, Infallible> {
+        match Self::find_job(&jobs, &query.job) {
+            Ok(job) => Ok(Self::update_backfill(&job, backfill_id, || Ok(()))),
+            Err(response) => Ok(response),
+        }
+    }
+
+    async fn set_backfill_status(
+        backfill_id: i64,
+        action: String,
+        query: JobQuery,
+        jobs: Jobs,
+    ) -> Result<Response, Infallible> {
+        let status = match action.as_str() {
+            "pause" => BackfillStatus::Paused,
+            "resume" => BackfillStatus::Running,
+            _ => {
+                return Ok(error_reply(
+                    format!("Unknown action: {}, expected pause or resume", action),
+                    StatusCode::NOT_FOUND,
+                ))
+            }
+        };
+
+        match Self::find_job(&jobs, &query.job) {
+            Ok(job) => Ok(Self::update_backfill(&job, backfill_id, || {
+                job.etl
+                    .job_manager()
+                    .set_backfill_status(backfill_id, status)
+            })),
+            Err(response) => Ok(response),
+        }
+    }
+
+    async fn set_backfill_throttle(
+        backfill_id: i64,
+        query: JobQuery,
+        request: ThrottleRequest,
+        jobs: Jobs,
+    ) -> Result<Response, Infallible> {
+        match Self::find_job(&jobs, &query.job) {
+            Ok(job) => Ok(Self::update_backfill(&job, backfill_id, || {
+                job.etl
+                    .job_manager()
+                    .set_backfill_throttle(backfill_id, request.throttle_ms)
+            })),
+            Err(response) => Ok(response),
+        }
+    }
+
+    /// Serve the admin API until the shutdown, requests in progress are finished first
+    pub async fn run(
+        &self,
+        jobs: Jobs,
+        topology: serde_json::Value,
+        shutdown: Shutdown,
+    ) -> eyre::Result<()> {
         log::info!("Starting WebAPI server for application administrating");
 
-        let health_check_route_root =
-            warp::get().map(|| warp::reply::with_status("health check OK", StatusCode::OK));
+        let topology_route = warp::get()
+            .and(warp::path!("topology"))
+            .map(move || reply::json(&topology));
+
+        let health_check_route_root = warp::get()
+            .and(warp::path::end())
+            .map(|| warp::reply::with_status("health check OK", StatusCode::OK));
+
+        let readiness_route = warp::get()
+            .and(warp::path!("readyz"))
+            .and(Self::with_jobs(jobs.clone()))
+            .and_then(Self::readiness);
 
+        let stall_timeout = self.stall_timeout;
+        let liveness_route = warp::get()
+            .and(warp::path!("livez"))
+            .and(Self::with_jobs(jobs.clone()))
+            .and(warp::any().map(move || stall_timeout))
+            .and_then(Self::liveness);
+
+        let dry_run = self.dry_run;
+        let token = self.api.token.clone();
         let request_processing_route = warp::post()
-            .and(warp::path("process"))
-            .and(warp::body::json())
-            .and(Self::with_sender(message_sender.clone()))
+            .and(warp::path!("process"))
+            .and(Self::authorized(token.clone()))
+            .and(warp::query::<ProcessQuery>())
+            .and(warp::body::content_length_limit(MAX_REQUEST_BYTES))
+            .and(warp::body::bytes())
+            .and(Self::with_jobs(jobs.clone()))
+            .and(warp::any().map(move || dry_run))
             .and_then(Self::request_processing);
 
+        let request_batch_processing_route = warp::post()
+            .and(warp::path!("process" / "batch"))
+            .and(Self::authorized(token.clone()))
+            .and(warp::query::<ProcessQuery>())
+            .and(warp::body::content_length_limit(MAX_BATCH_BYTES))
+            .and(warp::body::bytes())
+            .and(Self::with_jobs(jobs.clone()))
+            .and(warp::any().map(move || dry_run))
+            .and_then(Self::request_batch_processing);
+
+        let get_request_route = warp::get()
+            .and(warp::path!("process" / i64))
+            .and(warp::query::<JobQuery>())
+            .and(Self::with_jobs(jobs.clone()))
+            .and_then(Self::get_request);
+
+        // Backfill administration ---------------------------------------------------------
+        let create_backfill_route = warp::post()
+            .and(warp::path!("backfill"))
+            .and(Self::authorized(token.clone()))
+            .and(warp::query::<JobQuery>())
+            .and(warp::body::json())
+            .and(Self::with_jobs(jobs.clone()))
+            .and_then(Self::create_backfill);
+
+        let list_backfills_route = warp::get()
+            .and(warp::path!("backfill"))
+            .and(warp::query::<JobQuery>())
+            .and(Self::with_jobs(jobs.clone()))
+            .and_then(Self::list_backfills);
+
+        let get_backfill_route = warp::get()
+            .and(warp::path!("backfill" / i64))
+            .and(warp::query::<JobQuery>())
+            .and(Self::with_jobs(jobs.clone()))
+            .and_then(Self::get_backfill);
+
+        let set_backfill_status_route = warp::post()
+            .and(warp::path!("backfill" / i64 / String))
+            .and(Self::authorized(token.clone()))
+            .and(warp::query::<JobQuery>())
+            .and(Self::with_jobs(jobs.clone()))
+            .and_then(Self::set_backfill_status);
+
+        let set_backfill_throttle_route = warp::put()
+            .and(warp::path!("backfill" / i64 / "throttle"))
+            .and(Self::authorized(token.clone()))
+            .and(warp::query::<JobQuery>())
+            .and(warp::body::json())
+            .and(Self::with_jobs(jobs.clone()))
+            .and_then(Self::set_backfill_throttle);
+
+        let list_watermarks_route = warp::get()
+            .and(warp::path!("watermark"))
+            .and(warp::query::<JobQuery>())
+            .and(Self::with_jobs(jobs.clone()))
+            .and_then(Self::list_watermarks);
+
         log::info!("Starting HTTP server on port: {}", self.port);
 
-        let routes = health_check_route_root.or(request_processing_route);
+        let routes = topology_route
+            .or(create_backfill_route)
+            .or(list_backfills_route)
+            .or(get_backfill_route)
+            .or(set_backfill_status_route)
+            .or(set_backfill_throttle_route)
+            .or(list_watermarks_route)
+            .or(health_check_route_root)
+            .or(readiness_route)
+            .or(liveness_route)
+            .or(request_processing_route)
+            .or(request_batch_processing_route)
+            .or(get_request_route)
+            .recover(Self::unauthorized_reply);
 
+        let address = ([0, 0, 0, 0], self.port);
+        let stopped = async move { shutdown.requested().await };
         tokio::try_join!(self.setup(), async {
-            warp::serve(routes).run(([0, 0, 0, 0], self.port)).await;
-            Ok(())
+            #[cfg(feature = "api_tls")]
+            if let (Some(cert), Some(key)) = (&self.api.tls_cert, &self.api.tls_key) {
+                let mut server = warp::serve(routes).tls().cert_path(cert).key_path(key);
+                if let Some(client_ca) = &self.api.client_ca {
+                    server = server.client_auth_required_path(client_ca);
+                }
+                let (_, server) = server.try_bind_with_graceful_shutdown(address, stopped)?;
+                log::info!(
+                    "Serving HTTPS, client certificates: {}",
+                    self.api.client_ca.is_some()
+                );
+                server.await;
+                log::info!("HTTP server stopped");
+                return Ok(());
+            }
+
+            let (_, server) =
+                warp::serve(routes).try_bind_with_graceful_shutdown(address, stopped)?;
+            server.await;
+            log::info!("HTTP server stopped");
+            Ok::<_, eyre::Report>(())
         })?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = r#"{"DataStoreUpdated": {"table": "actions", "range": {"range": {"numeric": {"from": 1, "to": 10}}, "filters": {"chain_id": 1}}}}"#;
+
+    #[test]
+    fn test_parse_batch() {
+        let array = format!("[{}, {{\"Unknown\": {{}}}}]", MESSAGE);
+        let items = parse_batch(array.as_bytes()).unwrap();
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        assert!(items[1].is_err());
+
+        let ndjson = format!("{}\n\nnot json\n{}\n", MESSAGE, MESSAGE);
+        let items = parse_batch(ndjson.as_bytes()).unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(items[0].is_ok());
+        assert!(items[1].is_err());
+        assert!(items[2].is_ok());
+
+        assert!(parse_batch(b"[1, ").is_err());
+        assert!(parse_batch(b"[]").is_err());
+        assert!(parse_batch(b"\n").is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use tokio::sync::watch;
+
+/// Request to stop, shared by the tasks of the app: they stop taking new work and
+/// finish the current one
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+/// Side requesting the shutdown
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+impl ShutdownTrigger {
+    pub fn request(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+impl Shutdown {
+    pub fn channel() -> (ShutdownTrigger, Self) {
+        let (sender, receiver) = watch::channel(false);
+        (ShutdownTrigger(sender), Self(receiver))
+    }
+
+    /// Shutdown requested on SIGTERM, eg: a rollout, or SIGINT
+    pub fn on_signal() -> eyre::Result<Self> {
+        let (trigger, shutdown) = Self::channel();
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = terminate.recv() => log::info!("Received SIGTERM, shutting down"),
+                _ = tokio::signal::ctrl_c() => log::info!("Received SIGINT, shutting down"),
+            }
+            trigger.request();
+        });
+        Ok(shutdown)
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolve once the shutdown is requested
+    pub async fn requested(&self) {
+        let mut receiver = self.0.clone();
+        // NOTE: the trigger is only dropped with the app, keep waiting then
+        if receiver.wait_for(|requested| *requested).await.is_err() {
+            futures::future::pending::<()>().await;
+        }
+    }
+}
@@ -1,32 +1,90 @@
 mod elt_job_manager;
+mod handlers;
 pub mod messages;
+mod registry;
+pub mod topology;
 
 use async_trait::async_trait;
+use database::PgConnection;
 use database::RangeQuery;
 use database::Table;
+use database::TableWrites;
 pub use elt_job_manager::EtlJobManager;
-use kanal::AsyncSender;
+pub use handlers::Handlers;
+pub use handlers::MessageHook;
+pub use handlers::UpdateHandler;
 use messages::Message;
+pub use registry::EtlJobRegistration;
+use serde::Serialize;
+use std::ops::DerefMut;
+use std::sync::Mutex;
+use std::sync::TryLockError;
+
+/// What a message would have done, computed by running the handler in a rolled back sink transaction
+#[derive(Debug, Serialize)]
+pub struct DryRunReport {
+    /// Rows the handler would have written, per sink table
+    pub writes: Vec<TableWrites>,
+    /// Message the job would have emitted
+    pub message: Option<Message>,
+}
+
+/// Rows written per sink table & the message the handler would emit, see `ETLTrait::dry_run_changes`
+pub type DryRunOutput = (Vec<TableWrites>, Option<Message>);
+
+/// Error used to roll back the sink transaction of a dry run
+#[derive(Debug)]
+pub struct DryRunRollback;
+
+impl std::fmt::Display for DryRunRollback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "dry run rollback")
+    }
+}
+
+impl std::error::Error for DryRunRollback {}
+
+/// Check a connection shared by the handlers of a job. A connection locked by a handler is in
+/// use and assumed to work, the check does not wait for it
+pub fn check_connection(conn: &Mutex<PgConnection>) -> eyre::Result<()> {
+    let mut conn = match conn.try_lock() {
+        Ok(conn) => conn,
+        Err(TryLockError::WouldBlock) => return Ok(()),
+        Err(TryLockError::Poisoned(_)) => eyre::bail!("Connection poisoned by a failed handler"),
+    };
+    database::ping(conn.deref_mut())?;
+    Ok(())
+}
 
 #[async_trait]
 pub trait ETLTrait: Send + Sync + 'static {
-    fn new(
-        source: &str,
-        sink: &str,
-        job_manager: &str,
-        emitter: AsyncSender<Message>,
-    ) -> eyre::Result<Self>
+    fn new(source: &str, sink: &str, job_manager: EtlJobManager) -> eyre::Result<Self>
     where
         Self: Sized;
 
     /// Return the ID of the ETL job, ID must be unique
-    fn id() -> String;
+    fn id() -> String
+    where
+        Self: Sized;
+
+    /// Return the tables this job listens to
+    fn consumes() -> Vec<Table>
+    where
+        Self: Sized;
+
+    /// Return the tables this job writes to
+    fn produces() -> Vec<Table>
+    where
+        Self: Sized;
+
+    /// Return the tables this job listens to, same as `consumes` but callable on a job instance
+    fn consumed_tables(&self) -> Vec<Table>;
 
     /// Return EtlJobManager
     fn job_manager(&self) -> &EtlJobManager;
 
-    /// Return result emitter
-    fn emitter(&self) -> AsyncSender<Message>;
+    /// Check the source & sink connections of the job, see `check_connection`
+    fn check_connections(&self) -> eyre::Result<()>;
 
     /// Resume the ETL job
     async fn resume(&self) -> eyre::Result<()> {
@@ -63,35 +121,102 @@ pub trait ETLTrait: Send + Sync + 'static {
         range: RangeQuery,
     ) -> eyre::Result<Option<(Table, RangeQuery)>>;
 
-    /// Process the message from a saved etl-job and send the result to the emitter if possible
-    async fn process_message(&self, msg: Message, job_pk: i64) -> eyre::Result<()> {
-        log::info!("Processing message: \n{}", msg);
-        let emitter = self.emitter();
+    /// Handle rows of a consumed table deleted in the range, eg: remove the sink rows derived
+    /// from them. Return the message to emit
+    fn deleting_changes(&self, table: Table, range: RangeQuery) -> eyre::Result<Option<Message>>;
+
+    /// Handle a range of a consumed table invalidated upstream, eg: a chain reorg,
+    /// by dropping & recomputing the sink rows derived from it. Return the message to emit
+    fn invalidating_changes(
+        &self,
+        table: Table,
+        range: RangeQuery,
+    ) -> eyre::Result<Option<Message>>;
 
+    /// Run the handler of the message, return the message to emit
+    fn handle_message(&self, msg: Message) -> eyre::Result<Option<Message>> {
         match msg {
-            Message::DataStoreUpdated { table, range } => {
-                if let Some((updated_table, updated_range)) =
-                    self.processing_changes(table, range)?
-                {
-                    let result = Message::DataStoreUpdated {
-                        table: updated_table,
-                        range: updated_range,
-                    };
-                    emitter.send(result).await?;
-                } else {
-                    log::info!("No output");
-                }
+            Message::DataStoreUpdated { table, range } => Ok(self
+                .processing_changes(table, range)?
+                .map(|(table, range)| Message::DataStoreUpdated { table, range })),
+            Message::DataStoreDeleted { table, range } => self.deleting_changes(table, range),
+            Message::DataStoreInvalidated { table, range } => {
+                self.invalidating_changes(table, range)
             }
-        };
+        }
+    }
 
-        self.job_manager().mark_job_as_completed(job_pk)?;
+    /// Same as `handle_message`, but the sink transaction is always rolled back
+    /// and the job state is left untouched
+    fn dry_run_changes(&self, msg: Message) -> eyre::Result<DryRunOutput>;
+
+    /// Report what the message would write & emit, without publishing nor saving the job
+    async fn dry_run(&self, msg: Message) -> eyre::Result<DryRunReport> {
+        log::info!("Dry-running message: \n{}", msg);
+        let (writes, message) = self.dry_run_changes(msg)?;
+        Ok(DryRunReport { writes, message })
+    }
+
+    /// Check a message can be processed by this job: a valid range of a consumed table,
+    /// in the chains of its shard
+    fn validate_message(&self, msg: &Message) -> eyre::Result<()> {
+        msg.validate()?;
+        let consumed = self.consumed_tables();
+        if !consumed.contains(msg.table()) {
+            eyre::bail!(
+                "Table {} is not consumed by this job, expected one of: {:?}",
+                msg.table().name(),
+                consumed.iter().map(Table::name).collect::<Vec<_>>()
+            );
+        }
+        let shard = self.job_manager().shard();
+        if !shard.accepts(msg) {
+            eyre::bail!(
+                "Range is not in the chains {:?} of this job, filter it by chain_id",
+                shard.chain_ids()
+            );
+        }
+        Ok(())
+    }
+
+    /// Process the message from a saved etl-job, its result is queued in the outbox
+    /// with the completion of the job & its watermark, and published by the outbox relay
+    async fn process_message(&self, msg: Message, job_pk: i64) -> eyre::Result<()> {
+        log::info!("Processing message: \n{}", msg);
+        let result = self.handle_message(msg.clone())?;
+        if result.is_none() {
+            log::info!("No output");
+        }
+
+        let reorg = self
+            .job_manager()
+            .complete_job(job_pk, &msg, result.as_ref())?;
         log::info!("Job with id: {} completed", job_pk);
+        if let Some(depth) = reorg {
+            log::warn!("Reorg of {} recorded, depth: {}", msg.table().name(), depth);
+        }
         Ok(())
     }
 
     /// Process the message from the message queue
     async fn process_message_from_mq(&self, msg: Message) -> eyre::Result<()> {
         log::info!("Received message: \n{}", msg);
+        if !self.job_manager().shard().accepts(&msg) {
+            log::warn!(
+                "Skipping message of chain {:?}, job {} processes chains {:?}",
+                msg.chain_id(),
+                self.job_manager().job_id(),
+                self.job_manager().shard().chain_ids()
+            );
+            return Ok(());
+        }
+        if let Some(gap) = self.job_manager().watermark_gap(&msg)? {
+            log::warn!(
+                "Gap in {} between the watermark and the received range: {:?} was never processed",
+                msg.table().name(),
+                gap
+            );
+        }
         let etl_job = self.job_manager().save(&msg)?;
         self.process_message(msg, etl_job.id).await?;
         Ok(())
@@ -111,26 +236,48 @@ fn handle_data(
     todo!("Implement processing")
 }
 
+// Optional, called for DataStoreDeleted & DataStoreInvalidated messages, `Message` is imported by the macro.
+// Default to re-running `handle_data` on the range & invalidating its output downstream
+fn handle_deleted(
+    table: Table,
+    range: RangeQuery,
+    source: &mut PgConnection,
+    sink: &mut PgConnection,
+    state: &mut State,
+) -> eyre::Result<Option<Message>> {
+    todo!("Remove the sink rows derived from the deleted range")
+}
+
 create_etl_job!(
     id => "job_id_abc",
-    tier => 1,
+    consumes => [Table::Tier1(tier_1::Table::Actions)],
+    produces => [Table::Tier2(tier_2::Table::BuySell)],
+    // Optional, consumed tables with contiguous ranges whose watermarks & gaps are tracked
+    watermarks => [Table::Tier1(tier_1::Table::Actions)],
     state => State,
-    handle_data
+    handle_data,
+    on_delete => handle_deleted,
+    on_invalidate => handle_deleted,
 );
 */
 #[macro_export]
 macro_rules! create_etl_job {
     (
         id => $id:expr,
+        consumes => [$($consumes:expr),* $(,)?],
+        produces => [$($produces:expr),* $(,)?],
+        $(watermarks => [$($watermarks:expr),* $(,)?],)?
         state => $state:ident,
         $processing:expr
+        $(, on_delete => $on_delete:expr)?
+        $(, on_invalidate => $on_invalidate:expr)?
+        $(,)?
     ) => {
         use async_trait::async_trait;
         use common::messages::Message;
         use common::ETLTrait;
         use common::EtlJobManager;
         use database::create_pg_connection;
-        use kanal::AsyncSender;
         use std::ops::DerefMut;
         use std::sync::{Arc, Mutex};
 
@@ -139,7 +286,6 @@ macro_rules! create_etl_job {
             source: Arc<Mutex<PgConnection>>,
             sink: Arc<Mutex<PgConnection>>,
             jm: EtlJobManager,
-            emitter: AsyncSender<Message>,
             state: Arc<Mutex<$state>>,
         }
 
@@ -148,14 +294,13 @@ macro_rules! create_etl_job {
             fn new(
                 source: &str,
                 sink: &str,
-                job_manager: &str,
-                emitter: AsyncSender<Message>,
+                job_manager: EtlJobManager,
             ) -> eyre::Result<Self> {
                 Ok(Etl {
                     source: Arc::new(Mutex::new(create_pg_connection(source))),
                     sink: Arc::new(Mutex::new(create_pg_connection(sink))),
-                    jm: EtlJobManager::new(job_manager, $id),
-                    emitter,
+                    jm: job_manager
+                        .with_watermarks(vec![$($($watermarks),*)?], &Self::consumes())?,
                     state: Arc::new(Mutex::new($state::default())),
                 })
             }
@@ -164,12 +309,28 @@ macro_rules! create_etl_job {
                 $id.to_string()
             }
 
+            fn consumes() -> Vec<Table> {
+                vec![$($consumes),*]
+            }
+
+            fn produces() -> Vec<Table> {
+                vec![$($produces),*]
+            }
+
+            fn consumed_tables(&self) -> Vec<Table> {
+                Self::consumes()
+            }
+
             fn job_manager(&self) -> &EtlJobManager {
                 &self.jm
             }
 
-            fn emitter(&self) -> AsyncSender<Message> {
-                self.emitter.clone()
+            fn check_connections(&self) -> eyre::Result<()> {
+                use eyre::WrapErr;
+
+                common::check_connection(&self.source).wrap_err("Source database")?;
+                common::check_connection(&self.sink).wrap_err("Sink database")?;
+                Ok(())
             }
 
             fn processing_changes(
@@ -177,23 +338,83 @@ macro_rules! create_etl_job {
                 table: Table,
                 range: database::RangeQuery,
             ) -> eyre::Result<Option<(Table, database::RangeQuery)>> {
+                self.with_connections(|source_db, sink_db, state| {
+                    Self::handlers().updated(table, range, source_db, sink_db, state)
+                })
+            }
+
+            fn deleting_changes(
+                &self,
+                table: Table,
+                range: database::RangeQuery,
+            ) -> eyre::Result<Option<Message>> {
+                self.with_connections(|source_db, sink_db, state| {
+                    Self::handlers().deleted(table, range, source_db, sink_db, state)
+                })
+            }
+
+            fn invalidating_changes(
+                &self,
+                table: Table,
+                range: database::RangeQuery,
+            ) -> eyre::Result<Option<Message>> {
+                self.with_connections(|source_db, sink_db, state| {
+                    Self::handlers().invalidated(table, range, source_db, sink_db, state)
+                })
+            }
+
+            fn dry_run_changes(&self, msg: Message) -> eyre::Result<common::DryRunOutput> {
+                use database::Connection;
+
                 let mut source_db = self.source.lock().unwrap();
                 let source_db = source_db.deref_mut();
 
                 let mut sink_db = self.sink.lock().unwrap();
                 let sink_db = sink_db.deref_mut();
 
-                let mut state = self.state.lock().unwrap();
-                let state = state.deref_mut();
-
-                if let Some((updated_table, updated_query)) =
-                    $processing(table, range, source_db, sink_db, state)?
-                {
-                    Ok(Some((updated_table, updated_query)))
-                } else {
-                    Ok(None)
+                // NOTE: work on a copy so the dry run does not leak into the job state
+                let mut state = self.state.lock().unwrap().clone();
+
+                let mut report = None;
+                let result = sink_db.transaction::<(), eyre::Report, _>(|sink_db| {
+                    let output = Self::handlers().handle(msg, source_db, sink_db, &mut state)?;
+                    let writes = database::transaction_writes(sink_db)?;
+                    report = Some((writes, output));
+                    Err(common::DryRunRollback.into())
+                });
+
+                match (report, result) {
+                    (Some(report), _) => Ok(report),
+                    (None, Err(error)) => Err(error),
+                    (None, Ok(_)) => unreachable!("dry run transaction is always rolled back"),
+                }
+            }
+        }
+
+        impl Etl {
+            fn handlers() -> common::Handlers<$state> {
+                common::Handlers {
+                    on_update: $processing,
+                    on_delete: common::__etl_hook!($($on_delete)?),
+                    on_invalidate: common::__etl_hook!($($on_invalidate)?),
                 }
             }
+
+            /// Lock the connections & the state for a handler
+            fn with_connections<T>(
+                &self,
+                handler: impl FnOnce(&mut PgConnection, &mut PgConnection, &mut $state) -> eyre::Result<T>,
+            ) -> eyre::Result<T> {
+                let mut source_db = self.source.lock().unwrap();
+                let mut sink_db = self.sink.lock().unwrap();
+                let mut state = self.state.lock().unwrap();
+                handler(source_db.deref_mut(), sink_db.deref_mut(), state.deref_mut())
+            }
+        }
+
+        /// Registry entry used by etl-app to host this job
+        pub fn registration() -> common::EtlJobRegistration {
+            common::EtlJobRegistration::new::<Etl>()
         }
     };
 }
@@ -6,23 +6,81 @@ use std::fmt::Debug;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Message {
+    /// Rows of the table were inserted or updated in the range
     DataStoreUpdated { table: Table, range: RangeQuery },
+    /// Rows of the table were deleted in the range, eg: a truncation
+    DataStoreDeleted { table: Table, range: RangeQuery },
+    /// Rows of the table in the range can not be trusted anymore and are being rewritten,
+    /// eg: a chain reorg from block N. Derived rows must be dropped & recomputed
+    DataStoreInvalidated { table: Table, range: RangeQuery },
 }
 
-impl std::fmt::Display for Message {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Message {
+    /// Table the message is about, used as routing key
+    pub fn table(&self) -> &Table {
         match self {
-            Message::DataStoreUpdated { table, range } => {
-                write!(
-                    f,
-                    "** DataStoreUpdated: table: {:?}, range: {:?}",
-                    table, range
-                )
-            }
+            Message::DataStoreUpdated { table, .. }
+            | Message::DataStoreDeleted { table, .. }
+            | Message::DataStoreInvalidated { table, .. } => table,
+        }
+    }
+
+    pub fn range(&self) -> &RangeQuery {
+        match self {
+            Message::DataStoreUpdated { range, .. }
+            | Message::DataStoreDeleted { range, .. }
+            | Message::DataStoreInvalidated { range, .. } => range,
+        }
+    }
+
+    /// Chain of the range, from its `chain_id` filter
+    pub fn chain_id(&self) -> Option<i64> {
+        self.range()
+            .filters
+            .get("chain_id")
+            .and_then(serde_json::Value::as_i64)
+    }
+
+    /// Routing key of the message: the table name, followed by the chain when the range has one,
+    /// eg: actions.1
+    pub fn routing_key(&self) -> String {
+        match self.chain_id() {
+            Some(chain_id) => format!("{}.{}", self.table().name(), chain_id),
+            None => self.table().name(),
+        }
+    }
+
+    /// Check the range is ordered and has the range type & filters of its table
+    pub fn validate(&self) -> eyre::Result<()> {
+        let range = self.range();
+        if !range.range.validate() {
+            eyre::bail!("Range starts after its end: {:?}", range.range);
+        }
+        self.table().validate(range)
+    }
+
+    /// Name of the variant, eg: DataStoreUpdated
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Message::DataStoreUpdated { .. } => "DataStoreUpdated",
+            Message::DataStoreDeleted { .. } => "DataStoreDeleted",
+            Message::DataStoreInvalidated { .. } => "DataStoreInvalidated",
         }
     }
 }
 
+impl std::fmt::Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "** {}: table: {:?}, range: {:?}",
+            self.kind(),
+            self.table(),
+            self.range()
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,12 +136,103 @@ mod tests {
             }
         }"#;
         let deserialized: Message = serde_json::from_str(example_payload).unwrap();
-        #[allow(irrefutable_let_patterns)]
+        assert_eq!(deserialized.routing_key(), "actions");
         if let Message::DataStoreUpdated { table, range } = deserialized {
             assert_eq!(table, Table::Tier1(Tier1::Actions));
             assert_eq!(range.filters, serde_json::Value::Null);
         } else {
             panic!("Deserialization failed");
         }
+
+        let reorg_payload = r#"{
+            "DataStoreInvalidated": {
+                "table": "actions",
+                "range": {
+                    "range": { "numeric": { "from": 100, "to": 9223372036854775807 } },
+                    "filters": { "chain_id": 1 }
+                }
+            }
+        }"#;
+        let deserialized: Message = serde_json::from_str(reorg_payload).unwrap();
+        assert_eq!(deserialized.kind(), "DataStoreInvalidated");
+        assert_eq!(deserialized.table(), &Table::Tier1(Tier1::Actions));
+        assert_eq!(
+            deserialized.range().range,
+            Range::Numeric {
+                from: 100,
+                to: i64::MAX
+            }
+        );
+        assert_eq!(deserialized.chain_id(), Some(1));
+        assert_eq!(deserialized.routing_key(), "actions.1");
+    }
+
+    #[test]
+    fn test_validate_message() {
+        let msg = |range, filters| Message::DataStoreUpdated {
+            table: Table::Tier1(Tier1::Actions),
+            range: RangeQuery { range, filters },
+        };
+        let blocks = Range::Numeric { from: 1, to: 10 };
+
+        assert!(msg(blocks.clone(), json!({ "chain_id": 1 }))
+            .validate()
+            .is_ok());
+        // Reversed range
+        let reversed = Range::Numeric { from: 10, to: 1 };
+        assert!(msg(reversed, json!({ "chain_id": 1 })).validate().is_err());
+        // Actions are ranges of blocks
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let dates = Range::Date {
+            from: date,
+            to: date,
+        };
+        assert!(msg(dates, json!({ "chain_id": 1 })).validate().is_err());
+        // Actions are partitioned by chain
+        assert!(msg(blocks.clone(), json!(null)).validate().is_err());
+        assert!(msg(blocks.clone(), json!({ "chain_id": "1" }))
+            .validate()
+            .is_err());
+        assert!(msg(blocks, json!([1])).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_user_message() {
+        let buy_sell = |filters| Message::DataStoreUpdated {
+            table: Table::Tier2(database::tier_2::Table::BuySell),
+            range: RangeQuery {
+                range: Range::Numeric {
+                    from: 1_700_000_000,
+                    to: 1_700_086_400,
+                },
+                filters,
+            },
+        };
+
+        assert!(buy_sell(json!({ "chain_id": 1, "user": "0xa" }))
+            .validate()
+            .is_ok());
+        assert!(buy_sell(json!({ "chain_id": 1, "users": ["0xa", "0xb"] }))
+            .validate()
+            .is_ok());
+        // Buy-sell rows are queried per user
+        assert!(buy_sell(json!({ "chain_id": 1 })).validate().is_err());
+        assert!(buy_sell(json!({ "chain_id": 1, "users": [] }))
+            .validate()
+            .is_err());
+        assert!(buy_sell(json!({ "user": "0xa" })).validate().is_err());
+
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let balances = Message::DataStoreInvalidated {
+            table: Table::Tier3(database::tier_3::Table::BalancePerDate),
+            range: RangeQuery {
+                range: Range::Date {
+                    from: date,
+                    to: date,
+                },
+                filters: json!({ "chain_id": 1 }),
+            },
+        };
+        assert!(balances.validate().is_err());
     }
 }
@@ -0,0 +1,54 @@
+use crate::topology::Shard;
+use crate::ETLTrait;
+use crate::EtlJobManager;
+use database::Table;
+use std::sync::Arc;
+
+type EtlConstructor = fn(&str, &str, EtlJobManager) -> eyre::Result<Arc<dyn ETLTrait>>;
+
+/// Entry describing how to build an ETL job, exported by every ETL crate
+/// through `create_etl_job!` so that etl-app can host several jobs at once
+#[derive(Clone)]
+pub struct EtlJobRegistration {
+    pub id: String,
+    pub consumes: Vec<Table>,
+    pub produces: Vec<Table>,
+    constructor: EtlConstructor,
+}
+
+impl EtlJobRegistration {
+    pub fn new<T: ETLTrait>() -> Self {
+        Self {
+            id: T::id(),
+            consumes: T::consumes(),
+            produces: T::produces(),
+            constructor: |source, sink, job_manager| {
+                let etl = T::new(source, sink, job_manager)?;
+                Ok(Arc::new(etl))
+            },
+        }
+    }
+
+    /// Create a new instance of the ETL job processing the chains of the shard,
+    /// each instance owns its own state and job-manager scope
+    pub fn create(
+        &self,
+        source: &str,
+        sink: &str,
+        job_manager: &str,
+        shard: &Shard,
+    ) -> eyre::Result<Arc<dyn ETLTrait>> {
+        let job_manager = EtlJobManager::with_shard(job_manager, &self.id, shard.clone());
+        (self.constructor)(source, sink, job_manager)
+    }
+}
+
+impl std::fmt::Debug for EtlJobRegistration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EtlJobRegistration")
+            .field("id", &self.id)
+            .field("consumes", &self.consumes)
+            .field("produces", &self.produces)
+            .finish()
+    }
+}
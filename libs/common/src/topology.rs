@@ -0,0 +1,382 @@
+use crate::messages::Message;
+use crate::EtlJobRegistration;
+use database::Table;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// A job in the pipeline with the tables it reads from and writes to
+#[derive(Debug, Clone, Serialize)]
+pub struct JobNode {
+    pub id: String,
+    pub consumes: Vec<Table>,
+    pub produces: Vec<Table>,
+}
+
+/// Queue of a job and the routing keys (table names & chains) it must be bound to
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct QueueBinding {
+    pub job_id: String,
+    pub queue: String,
+    pub routing_keys: Vec<String>,
+}
+
+impl QueueBinding {
+    /// Whether a message published with the routing key reaches the queue, like a topic exchange:
+    /// a trailing `.#` matches the key without suffix or with any suffix
+    pub fn matches(&self, routing_key: &str) -> bool {
+        self.routing_keys
+            .iter()
+            .any(|pattern| match pattern.strip_suffix(".#") {
+                Some(prefix) => {
+                    routing_key == prefix
+                        || routing_key
+                            .strip_prefix(prefix)
+                            .is_some_and(|suffix| suffix.starts_with('.'))
+                }
+                None => routing_key == pattern,
+            })
+    }
+
+    /// Names of the tables the queue is bound to
+    pub fn tables(&self) -> Vec<String> {
+        let mut tables: Vec<String> = vec![];
+        for routing_key in &self.routing_keys {
+            let table = routing_key
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            if !tables.contains(&table) {
+                tables.push(table);
+            }
+        }
+        tables
+    }
+}
+
+/// Chains processed by an instance of a job, all of them when empty.
+/// Every shard of a job has its own queue & job-manager scope, so each chain progresses independently
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Shard {
+    chains: Vec<i64>,
+}
+
+impl Shard {
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn chains(mut chains: Vec<i64>) -> Self {
+        chains.sort_unstable();
+        chains.dedup();
+        Self { chains }
+    }
+
+    pub fn chain_ids(&self) -> &[i64] {
+        &self.chains
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.chains.is_empty()
+    }
+
+    /// Job ID scoped to the shard, eg: job_id_abc.chain_1_137
+    pub fn scope(&self, job_id: &str) -> String {
+        if self.is_all() {
+            return job_id.to_string();
+        }
+        let chains: Vec<String> = self.chains.iter().map(i64::to_string).collect();
+        format!("{}.chain_{}", job_id, chains.join("_"))
+    }
+
+    /// Shards processing the same chain would process its messages twice
+    pub fn overlaps(&self, other: &Shard) -> bool {
+        self.is_all()
+            || other.is_all()
+            || self.chains.iter().any(|chain| other.chains.contains(chain))
+    }
+
+    /// Rows without a chain are only processed by the shards of all the chains
+    pub fn contains(&self, chain_id: Option<i64>) -> bool {
+        self.is_all() || chain_id.is_some_and(|chain_id| self.chains.contains(&chain_id))
+    }
+
+    pub fn accepts(&self, msg: &Message) -> bool {
+        self.contains(msg.chain_id())
+    }
+
+    /// Routing keys of a consumed table: any chain, or one key per chain of the shard
+    pub fn routing_keys(&self, table: &Table) -> Vec<String> {
+        if self.is_all() {
+            return vec![format!("{}.#", table.name())];
+        }
+        self.chains
+            .iter()
+            .map(|chain_id| format!("{}.{}", table.name(), chain_id))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct TopologyReport {
+    /// Cycles found in the graph, each one as a path of table names
+    pub cycles: Vec<Vec<String>>,
+    /// Tables consumed by some job but produced by none, eg: populated by an external indexer
+    pub source_tables: Vec<String>,
+    /// Tables produced by some job but consumed by none
+    pub sink_tables: Vec<String>,
+    /// Compiled-in tables that no job consumes nor produces
+    pub orphan_tables: Vec<String>,
+}
+
+impl TopologyReport {
+    pub fn is_valid(&self) -> bool {
+        self.cycles.is_empty()
+    }
+}
+
+/// DAG of the ETL pipeline, assembled from the consumes/produces declaration of every job
+#[derive(Debug, Clone, Serialize)]
+pub struct Topology {
+    pub jobs: Vec<JobNode>,
+}
+
+impl Topology {
+    pub fn new(jobs: Vec<JobNode>) -> Self {
+        Self { jobs }
+    }
+
+    pub fn from_registry(registry: &[EtlJobRegistration]) -> Self {
+        let jobs = registry
+            .iter()
+            .map(|job| JobNode {
+                id: job.id.clone(),
+                consumes: job.consumes.clone(),
+                produces: job.produces.clone(),
+            })
+            .collect();
+        Self::new(jobs)
+    }
+
+    /// Name of the queue consumed by a job
+    pub fn queue_name(job_id: &str) -> String {
+        format!("etl_{}", job_id)
+    }
+
+    /// Edges between tables: consumed table -> tables produced from it
+    fn edges(&self) -> HashMap<String, Vec<String>> {
+        let mut edges: HashMap<String, Vec<String>> = HashMap::new();
+        for job in &self.jobs {
+            for input in &job.consumes {
+                let outputs = edges.entry(input.name()).or_default();
+                for output in &job.produces {
+                    if !outputs.contains(&output.name()) {
+                        outputs.push(output.name());
+                    }
+                }
+            }
+        }
+        edges
+    }
+
+    fn find_cycles(&self) -> Vec<Vec<String>> {
+        let edges = self.edges();
+        let mut nodes: Vec<&String> = edges.keys().collect();
+        nodes.sort();
+
+        let mut cycles = vec![];
+        let mut done = HashSet::new();
+
+        fn visit(
+            node: &str,
+            edges: &HashMap<String, Vec<String>>,
+            path: &mut Vec<String>,
+            done: &mut HashSet<String>,
+            cycles: &mut Vec<Vec<String>>,
+        ) {
+            if let Some(start) = path.iter().position(|n| n == node) {
+                let mut cycle = path[start..].to_vec();
+                cycle.push(node.to_string());
+                cycles.push(cycle);
+                return;
+            }
+
+            if done.contains(node) {
+                return;
+            }
+
+            path.push(node.to_string());
+            for next in edges.get(node).into_iter().flatten() {
+                visit(next, edges, path, done, cycles);
+            }
+            path.pop();
+            done.insert(node.to_string());
+        }
+
+        for node in nodes {
+            visit(node, &edges, &mut vec![], &mut done, &mut cycles);
+        }
+
+        cycles
+    }
+
+    /// Validate the graph against the tables compiled in the database crate
+    pub fn validate(&self, known_tables: &[Table]) -> TopologyReport {
+        let consumed: HashSet<String> = self
+            .jobs
+            .iter()
+            .flat_map(|job| job.consumes.iter().map(Table::name))
+            .collect();
+        let produced: HashSet<String> = self
+            .jobs
+            .iter()
+            .flat_map(|job| job.produces.iter().map(Table::name))
+            .collect();
+
+        let mut source_tables: Vec<String> = consumed.difference(&produced).cloned().collect();
+        let mut sink_tables: Vec<String> = produced.difference(&consumed).cloned().collect();
+        let mut orphan_tables: Vec<String> = known_tables
+            .iter()
+            .map(Table::name)
+            .filter(|table| !consumed.contains(table) && !produced.contains(table))
+            .collect();
+
+        source_tables.sort();
+        sink_tables.sort();
+        orphan_tables.sort();
+
+        TopologyReport {
+            cycles: self.find_cycles(),
+            source_tables,
+            sink_tables,
+            orphan_tables,
+        }
+    }
+
+    /// Queue bindings of the jobs processing all the chains: every job owns one queue,
+    /// bound to the tables it consumes
+    pub fn bindings(&self) -> Vec<QueueBinding> {
+        self.jobs
+            .iter()
+            .map(|job| Self::job_binding(job, &Shard::all()))
+            .collect()
+    }
+
+    /// Queue binding of a shard of the job, its queue is bound to the chains of the shard only
+    pub fn binding(&self, job_id: &str, shard: &Shard) -> Option<QueueBinding> {
+        self.jobs
+            .iter()
+            .find(|job| job.id == job_id)
+            .map(|job| Self::job_binding(job, shard))
+    }
+
+    fn job_binding(job: &JobNode, shard: &Shard) -> QueueBinding {
+        QueueBinding {
+            job_id: shard.scope(&job.id),
+            queue: Self::queue_name(&shard.scope(&job.id)),
+            routing_keys: job
+                .consumes
+                .iter()
+                .flat_map(|table| shard.routing_keys(table))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::tier_1;
+    use database::tier_2;
+    use database::tier_3;
+
+    fn node(id: &str, consumes: Vec<Table>, produces: Vec<Table>) -> JobNode {
+        JobNode {
+            id: id.to_string(),
+            consumes,
+            produces,
+        }
+    }
+
+    #[test]
+    fn test_validate_tier_chain() {
+        let actions = Table::Tier1(tier_1::Table::Actions);
+        let buy_sell = Table::Tier2(tier_2::Table::BuySell);
+        let balance = Table::Tier3(tier_3::Table::BalancePerDate);
+
+        let topology = Topology::new(vec![
+            node("job_1", vec![actions.clone()], vec![buy_sell.clone()]),
+            node("job_2", vec![buy_sell.clone()], vec![balance.clone()]),
+        ]);
+
+        let report = topology.validate(&[actions, buy_sell, balance]);
+        assert!(report.is_valid());
+        assert_eq!(report.source_tables, vec!["actions"]);
+        assert_eq!(report.sink_tables, vec!["balanceperdate"]);
+        assert!(report.orphan_tables.is_empty());
+
+        let bindings = topology.bindings();
+        assert_eq!(
+            bindings[1],
+            QueueBinding {
+                job_id: "job_2".to_string(),
+                queue: "etl_job_2".to_string(),
+                routing_keys: vec!["buysell.#".to_string()],
+            }
+        );
+
+        let shard = Shard::chains(vec![137, 1, 137]);
+        assert_eq!(
+            topology.binding("job_1", &shard),
+            Some(QueueBinding {
+                job_id: "job_1.chain_1_137".to_string(),
+                queue: "etl_job_1.chain_1_137".to_string(),
+                routing_keys: vec!["actions.1".to_string(), "actions.137".to_string()],
+            })
+        );
+
+        let msg = |filters| Message::DataStoreUpdated {
+            table: Table::Tier1(tier_1::Table::Actions),
+            range: database::RangeQuery {
+                filters,
+                ..Default::default()
+            },
+        };
+        assert!(shard.accepts(&msg(serde_json::json!({ "chain_id": 137 }))));
+        assert!(!shard.accepts(&msg(serde_json::json!({ "chain_id": 10 }))));
+        assert!(!shard.accepts(&msg(serde_json::Value::Null)));
+        assert!(Shard::all().accepts(&msg(serde_json::Value::Null)));
+        let binding = topology.binding("job_1", &shard).unwrap();
+        assert!(binding.matches("actions.137"));
+        assert!(!binding.matches("actions.10"));
+        assert!(!binding.matches("actions"));
+        assert_eq!(binding.tables(), vec!["actions"]);
+        assert!(bindings[1].matches("buysell"));
+        assert!(bindings[1].matches("buysell.1"));
+        assert!(!bindings[1].matches("buysells.1"));
+        assert!(shard.overlaps(&Shard::chains(vec![1])));
+        assert!(!shard.overlaps(&Shard::chains(vec![10])));
+    }
+
+    #[test]
+    fn test_detect_cycles_and_orphans() {
+        let actions = Table::Tier1(tier_1::Table::Actions);
+        let buy_sell = Table::Tier2(tier_2::Table::BuySell);
+        let balance = Table::Tier3(tier_3::Table::BalancePerDate);
+
+        let topology = Topology::new(vec![
+            node("job_1", vec![buy_sell.clone()], vec![balance.clone()]),
+            node("job_2", vec![balance.clone()], vec![buy_sell.clone()]),
+        ]);
+
+        let report = topology.validate(&[actions, buy_sell, balance]);
+        assert!(!report.is_valid());
+        assert_eq!(
+            report.cycles,
+            vec![vec!["balanceperdate", "buysell", "balanceperdate"]]
+        );
+        assert_eq!(report.orphan_tables, vec!["actions"]);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::schema;
+use crate::sql;
+use crate::sql::SqlSchema;
+use std::path::Path;
+
+/// Migration sets, one directory per tier feature plus job_manager
+pub const MIGRATIONS: &str = "database/migrations";
+
+/// Disagreements between the `diesel::table!` schemas and the migrations
+#[derive(Debug, Default)]
+pub struct Report {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+pub fn check(root: &Path) -> eyre::Result<Report> {
+    let migrations = SqlSchema::from_migrations(&root.join(MIGRATIONS))?;
+    let schemas = schema::load_schemas(root)?;
+    let mut report = Report::default();
+
+    for table in schemas {
+        let location = format!("{}: {}", table.file.display(), table.name);
+        let Some(sql_table) = migrations.table(&table.name) else {
+            report
+                .warnings
+                .push(format!("{}: not created by any migration", location));
+            continue;
+        };
+
+        if table.primary_key != sql_table.primary_key {
+            report.errors.push(format!(
+                "{}: primary key is {:?}, migrations declare {:?}",
+                location, table.primary_key, sql_table.primary_key
+            ));
+        }
+
+        for column in &table.columns {
+            let Some(sql_column) = sql_table.column(&column.name) else {
+                report.errors.push(format!(
+                    "{}.{}: column not found in migrations",
+                    location, column.name
+                ));
+                continue;
+            };
+
+            match sql::diesel_type(&sql_column.sql_type, &migrations) {
+                Some(expected)
+                    if schema::normalize_type(&expected)
+                        == schema::normalize_type(&column.diesel_type) => {}
+                Some(expected) => report.errors.push(format!(
+                    "{}.{}: mapped as {}, but the column is {} ({})",
+                    location, column.name, column.diesel_type, sql_column.sql_type, expected
+                )),
+                None => report.warnings.push(format!(
+                    "{}.{}: unknown SQL type {}",
+                    location, column.name, sql_column.sql_type
+                )),
+            }
+
+            if column.nullable != sql_column.nullable {
+                report.errors.push(format!(
+                    "{}.{}: nullable is {} in schema, {} in migrations",
+                    location, column.name, column.nullable, sql_column.nullable
+                ));
+            }
+        }
+
+        for sql_column in &sql_table.columns {
+            if !table.columns.iter().any(|c| c.name == sql_column.name) {
+                report.errors.push(format!(
+                    "{}.{}: column missing from schema",
+                    location, sql_column.name
+                ));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+pub fn run(root: &Path) -> eyre::Result<()> {
+    let report = check(root)?;
+    for warning in &report.warnings {
+        println!("warning: {}", warning);
+    }
+    for error in &report.errors {
+        println!("error: {}", error);
+    }
+
+    if !report.errors.is_empty() {
+        eyre::bail!(
+            "Schemas and migrations disagree: {} error(s)",
+            report.errors.len()
+        );
+    }
+    println!("Schemas agree with migrations");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace;
+
+    #[test]
+    fn test_schemas_agree_with_migrations() {
+        let report = check(&workspace::root()).unwrap();
+        assert!(report.errors.is_empty(), "{:#?}", report.errors);
+    }
+}
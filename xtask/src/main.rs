@@ -0,0 +1,60 @@
+mod check_schema;
+mod new_etl;
+mod new_table;
+mod schema;
+mod sql;
+mod workspace;
+
+use clap::Parser;
+use clap::Subcommand;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Development tasks of the ETL workspace", long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create a new ETL crate from crates/__template__ and wire it into the workspace & etl-app
+    NewEtl {
+        /// Name of the crate, also used as job ID
+        #[arg(long)]
+        name: String,
+        /// Database features (tiers) the crate needs, eg: tier_1,tier_2
+        #[arg(long, value_delimiter = ',', required = true)]
+        tables: Vec<String>,
+        /// Tables the job listens to, eg: actions
+        #[arg(long, value_delimiter = ',', required = true)]
+        consumes: Vec<String>,
+        /// Tables the job writes to, eg: buysell
+        #[arg(long, value_delimiter = ',')]
+        produces: Vec<String>,
+    },
+    /// Generate the schema, model, Table variant, RowStream & upsert of a table from its migration
+    NewTable {
+        /// TOML spec of the table, see README
+        #[arg(long)]
+        spec: PathBuf,
+    },
+    /// Check that the diesel schemas in database/src agree with the migrations
+    CheckSchema,
+}
+
+fn main() -> eyre::Result<()> {
+    let Args { command } = Args::parse();
+    let root = workspace::root();
+
+    match command {
+        Command::NewEtl {
+            name,
+            tables,
+            consumes,
+            produces,
+        } => new_etl::run(&root, &name, &tables, &consumes, &produces),
+        Command::NewTable { spec } => new_table::run(&root, &spec),
+        Command::CheckSchema => check_schema::run(&root),
+    }
+}
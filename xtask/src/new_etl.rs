@@ -0,0 +1,227 @@
+use crate::workspace;
+use std::path::Path;
+use toml_edit::value;
+use toml_edit::InlineTable;
+use toml_edit::Item;
+use toml_edit::Value;
+
+const TEMPLATE: &str = "crates/__template__";
+const TEMPLATE_FILES: [&str; 2] = ["Cargo.toml", "src/lib.rs"];
+
+/// Values substituted in the template files
+struct Template {
+    name: String,
+    features: String,
+    consumes: String,
+    produces: String,
+}
+
+impl Template {
+    fn render(&self, content: &str) -> String {
+        content
+            .replace("__template__", &self.name)
+            .replace("__features__", &self.features)
+            .replace("__consumes__", &self.consumes)
+            .replace("__produces__", &self.produces)
+    }
+}
+
+fn validate_name(root: &Path, name: &str) -> eyre::Result<()> {
+    let valid = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        eyre::bail!(
+            "Invalid name: {}, use lowercase letters, digits and underscores",
+            name
+        );
+    }
+
+    if root.join("crates").join(name).exists() {
+        eyre::bail!("Crate crates/{} already exists", name);
+    }
+
+    Ok(())
+}
+
+/// Check the tables against the database features, and return the template values
+fn build_template(
+    root: &Path,
+    name: &str,
+    tables: &[String],
+    consumes: &[String],
+    produces: &[String],
+) -> eyre::Result<Template> {
+    let features = workspace::database_features(root)?;
+    for table in tables {
+        if !features.contains(table) {
+            eyre::bail!(
+                "Unknown database feature: {}, available features: {:?}",
+                table,
+                features
+            );
+        }
+    }
+
+    let exprs = |names: &[String]| -> eyre::Result<String> {
+        let mut result = vec![];
+        for name in names {
+            let table = workspace::find_table(name)?;
+            let feature = workspace::table_feature(&table);
+            if !tables.contains(&feature) {
+                eyre::bail!("Table {} needs the database feature {}", name, feature);
+            }
+            result.push(workspace::table_expr(&table));
+        }
+        Ok(result.join(", "))
+    };
+
+    Ok(Template {
+        name: name.to_string(),
+        features: tables
+            .iter()
+            .map(|table| format!("\"{}\"", table))
+            .collect::<Vec<_>>()
+            .join(", "),
+        consumes: exprs(consumes)?,
+        produces: exprs(produces)?,
+    })
+}
+
+fn create_crate(root: &Path, template: &Template) -> eyre::Result<()> {
+    let crate_dir = root.join("crates").join(&template.name);
+    for file in TEMPLATE_FILES {
+        let content = std::fs::read_to_string(root.join(TEMPLATE).join(file))?;
+        let target = crate_dir.join(file);
+        std::fs::create_dir_all(target.parent().unwrap())?;
+        std::fs::write(&target, template.render(&content))?;
+        println!("Created {}", target.display());
+    }
+    Ok(())
+}
+
+/// Add the crate to the workspace members & workspace dependencies
+fn register_in_workspace(root: &Path, name: &str) -> eyre::Result<()> {
+    let path = root.join("Cargo.toml");
+    let mut manifest = workspace::read_toml(&path)?;
+
+    let members = manifest["workspace"]["members"]
+        .as_array_mut()
+        .ok_or_else(|| eyre::eyre!("workspace.members is not an array"))?;
+    let member = format!("crates/{}", name);
+    let position = members
+        .iter()
+        .collect::<Vec<_>>()
+        .iter()
+        .rposition(|m| m.as_str().is_some_and(|m| m.starts_with("crates/")))
+        .map(|index| index + 1)
+        .unwrap_or(members.len());
+    members.insert_formatted(position, Value::from(member).decorated("\n    ", ""));
+
+    let mut dependency = InlineTable::new();
+    dependency.insert("path", Value::from(format!("crates/{}", name)));
+    manifest["workspace"]["dependencies"][name] = value(dependency);
+
+    workspace::write_toml(&path, &manifest)?;
+    println!("Registered crates/{} in workspace", name);
+    Ok(())
+}
+
+/// Add the crate as an optional dependency of etl-app, which makes it a feature
+fn register_in_etl_app(root: &Path, name: &str) -> eyre::Result<()> {
+    let path = root.join("etl-app/Cargo.toml");
+    let mut manifest = workspace::read_toml(&path)?;
+
+    let mut dependency = InlineTable::new();
+    dependency.insert("workspace", Value::from(true));
+    dependency.insert("optional", Value::from(true));
+    manifest["dependencies"][name] = Item::Value(Value::InlineTable(dependency));
+
+    workspace::write_toml(&path, &manifest)?;
+    println!("Registered {} as etl-app feature", name);
+    Ok(())
+}
+
+/// Add the crate to the job registry of etl-app
+fn register_in_registry(root: &Path, name: &str) -> eyre::Result<()> {
+    let path = root.join("etl-app/src/jobs.rs");
+    let content = std::fs::read_to_string(&path)?;
+
+    let anchor = "\n    jobs\n}";
+    let position = content
+        .find(anchor)
+        .ok_or_else(|| eyre::eyre!("Job registry not found in {}", path.display()))?;
+
+    let entry = format!(
+        "\n    #[cfg(feature = \"{name}\")]\n    jobs.push({name}::registration());\n",
+        name = name
+    );
+    let mut updated = content.clone();
+    updated.insert_str(position, &entry);
+
+    std::fs::write(&path, updated)?;
+    println!("Registered {} in etl-app job registry", name);
+    Ok(())
+}
+
+pub fn run(
+    root: &Path,
+    name: &str,
+    tables: &[String],
+    consumes: &[String],
+    produces: &[String],
+) -> eyre::Result<()> {
+    validate_name(root, name)?;
+    let template = build_template(root, name, tables, consumes, produces)?;
+
+    create_crate(root, &template)?;
+    register_in_workspace(root, name)?;
+    register_in_etl_app(root, name)?;
+    register_in_registry(root, name)?;
+
+    workspace::format(root, name);
+
+    println!(
+        "ETL {} created, build it with: cargo build -p etl-app -F {}",
+        name, name
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_template() {
+        let root = workspace::root();
+        let template = build_template(
+            &root,
+            "new_job",
+            &["tier_2".to_string(), "tier_3".to_string()],
+            &["buysell".to_string()],
+            &["balanceperdate".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(template.features, "\"tier_2\", \"tier_3\"");
+        assert_eq!(
+            template.render("consumes => [__consumes__], produces => [__produces__]"),
+            "consumes => [Table::Tier2(tier_2::Table::BuySell)], produces => [Table::Tier3(tier_3::Table::BalancePerDate)]"
+        );
+
+        // actions belongs to tier_1 which is not requested
+        assert!(build_template(
+            &root,
+            "new_job",
+            &["tier_2".to_string()],
+            &["actions".to_string()],
+            &[],
+        )
+        .is_err());
+        assert!(build_template(&root, "new_job", &["tier_9".to_string()], &[], &[]).is_err());
+        assert!(validate_name(&root, "Bad-Name").is_err());
+        assert!(validate_name(&root, "action_job").is_err());
+    }
+}
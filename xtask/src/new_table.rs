@@ -0,0 +1,782 @@
+use crate::check_schema;
+use crate::schema;
+use crate::sql;
+use crate::sql::camel_case;
+use crate::sql::SqlEnum;
+use crate::sql::SqlSchema;
+use crate::workspace;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+use toml_edit::Array;
+
+/// TOML spec of a generated table
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TableSpec {
+    /// Migration creating the table, relative to the workspace root
+    pub migration: PathBuf,
+    pub table: String,
+    /// Database feature the table belongs to, eg: tier_2. Created if missing
+    pub tier: String,
+    /// Model struct, default to the table name in CamelCase
+    pub model: Option<String>,
+    /// Column the RangeQuery applies to: BIGINT -> numeric, TIMESTAMP -> datetime, DATE -> date
+    pub range: String,
+    /// Columns matched for equality against the RangeQuery filters
+    #[serde(default)]
+    pub filters: Vec<String>,
+    /// Upsert conflict target, default to the primary key
+    pub conflict: Option<Vec<String>>,
+    /// Rust names of the Postgres enums, default to the type name in CamelCase
+    #[serde(default)]
+    pub enums: BTreeMap<String, String>,
+}
+
+/// Names used by the generated functions, which the glob imported columns would shadow
+const RESERVED_NAMES: [&str; 6] = [
+    "rows",
+    "pool",
+    "query",
+    "query_filter",
+    "range_from",
+    "range_to",
+];
+
+struct Field {
+    name: String,
+    diesel_type: String,
+    rust_type: String,
+    nullable: bool,
+}
+
+/// Code generated for a table
+#[derive(Debug)]
+struct Generated {
+    /// `diesel::table!` block appended to schemas.rs
+    schema: String,
+    /// Diesel SQL types of the Postgres enums: (name, declaration)
+    sql_types: Vec<(String, String)>,
+    /// Content of tier_N/<table>.rs
+    model: String,
+    /// Variant of the tier Table enum
+    variant: String,
+}
+
+fn rust_type(diesel_type: &str, imports: &mut BTreeSet<String>) -> Option<String> {
+    let rust_type = match diesel_type {
+        "BigInt" => "i64",
+        "Integer" => "i32",
+        "SmallInt" => "i16",
+        "VarChar" => "String",
+        "Float" => "f32",
+        "Double" => "f64",
+        "Bool" => "bool",
+        "Bytea" => "Vec<u8>",
+        "Jsonb" | "Json" => "serde_json::Value",
+        "Numeric" => {
+            imports.insert("bigdecimal::BigDecimal".to_string());
+            "BigDecimal"
+        }
+        "Timestamp" | "Timestamptz" => {
+            imports.insert("chrono::NaiveDateTime".to_string());
+            "NaiveDateTime"
+        }
+        "Date" => {
+            imports.insert("chrono::NaiveDate".to_string());
+            "NaiveDate"
+        }
+        _ => return None,
+    };
+    Some(rust_type.to_string())
+}
+
+fn string_list(items: &[String]) -> String {
+    let items = items
+        .iter()
+        .map(|item| format!("\"{}\"", item))
+        .collect::<Vec<_>>();
+    format!("&[{}]", items.join(", "))
+}
+
+/// Expression writing `value` as COPY text
+fn copy_text(field: &Field, value: &str) -> String {
+    match (field.rust_type.as_str(), field.diesel_type.as_str()) {
+        ("String", _) => format!("{}.clone()", value),
+        ("Vec<u8>", _) => format!(
+            "format!(\"\\\\x{{}}\", {}.iter().map(|byte| format!(\"{{:02x}}\", byte)).collect::<Vec<_>>().concat())",
+            value
+        ),
+        // NOTE: stored as UTC, like diesel does for Timestamptz
+        (_, "Timestamptz") => format!("format!(\"{{}}+00\", {})", value),
+        _ => format!("{}.to_string()", value),
+    }
+}
+
+fn render_sql_type(sql_enum: &SqlEnum) -> String {
+    format!(
+        "    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = \"{name}\"))]
+    pub struct {sql_type};
+",
+        name = sql_enum.name,
+        sql_type = camel_case(&sql_enum.name),
+    )
+}
+
+fn render_enum(sql_enum: &SqlEnum, name: &str) -> String {
+    let sql_type = camel_case(&sql_enum.name);
+    let variants = sql_enum
+        .values
+        .iter()
+        .map(|value| {
+            format!(
+                "    #[strum(serialize = \"{value}\")]\n    #[serde(rename = \"{value}\")]\n    {variant},\n",
+                value = value,
+                variant = camel_case(value)
+            )
+        })
+        .collect::<Vec<_>>()
+        .concat();
+
+    format!(
+        "/// Postgres enum `{sql_name}`
+#[derive(
+    EnumString, Display, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = schemas::sql_types::{sql_type})]
+pub enum {name} {{
+{variants}}}
+
+impl ToSql<schemas::sql_types::{sql_type}, Pg> for {name} {{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> diesel::serialize::Result {{
+        out.write_all(self.to_string().as_bytes())?;
+        Ok(IsNull::No)
+    }}
+}}
+
+impl FromSql<schemas::sql_types::{sql_type}, Pg> for {name} {{
+    fn from_sql(bytes: PgValue<'_>) -> diesel::deserialize::Result<Self> {{
+        let value = std::str::from_utf8(bytes.as_bytes())?;
+        Ok(value.parse()?)
+    }}
+}}
+
+",
+        sql_name = sql_enum.name,
+    )
+}
+
+/// Generate the code of the table, `existing_enums` are Rust enums already defined in the tier
+fn generate(
+    spec: &TableSpec,
+    migrations: &SqlSchema,
+    existing_enums: &[String],
+) -> eyre::Result<Generated> {
+    let table = migrations
+        .table(&spec.table)
+        .ok_or_else(|| eyre::eyre!("Table {} is not created by the migrations", spec.table))?;
+    if table.primary_key.is_empty() {
+        eyre::bail!(
+            "Table {} has no primary key, required by diesel",
+            spec.table
+        );
+    }
+
+    let model = spec
+        .model
+        .clone()
+        .unwrap_or_else(|| camel_case(&spec.table));
+    let mut imports = BTreeSet::new();
+    let mut enums = vec![];
+    let mut fields = vec![];
+
+    for column in &table.columns {
+        if RESERVED_NAMES.contains(&column.name.as_str()) {
+            eyre::bail!(
+                "Column name {} is reserved by the generated code",
+                column.name
+            );
+        }
+
+        let diesel_type = sql::diesel_type(&column.sql_type, migrations).ok_or_else(|| {
+            eyre::eyre!(
+                "Unsupported SQL type {} of column {}",
+                column.sql_type,
+                column.name
+            )
+        })?;
+
+        let rust_type = match migrations.enum_type(&column.sql_type) {
+            Some(sql_enum) => {
+                let name = spec
+                    .enums
+                    .get(&sql_enum.name)
+                    .cloned()
+                    .unwrap_or_else(|| diesel_type.clone());
+                if !enums
+                    .iter()
+                    .any(|(e, _): &(SqlEnum, String)| e.name == sql_enum.name)
+                {
+                    enums.push((sql_enum.clone(), name.clone()));
+                }
+                name
+            }
+            None => rust_type(&diesel_type, &mut imports).unwrap(),
+        };
+
+        fields.push(Field {
+            name: column.name.clone(),
+            diesel_type,
+            rust_type,
+            nullable: column.nullable,
+        });
+    }
+
+    let field = |name: &str| -> eyre::Result<&Field> {
+        fields
+            .iter()
+            .find(|field| field.name == name)
+            .ok_or_else(|| eyre::eyre!("Unknown column {} in table {}", name, spec.table))
+    };
+
+    let range_field = field(&spec.range)?;
+    if range_field.nullable {
+        eyre::bail!("Range column {} must be NOT NULL", spec.range);
+    }
+    let range_kind = match range_field.diesel_type.as_str() {
+        "BigInt" => "Numeric",
+        "Timestamp" | "Timestamptz" => "DateTime",
+        "Date" => "Date",
+        other => eyre::bail!(
+            "Range column {} must be BIGINT, TIMESTAMP or DATE, found {}",
+            spec.range,
+            other
+        ),
+    };
+
+    for filter in &spec.filters {
+        if field(filter)?.nullable {
+            eyre::bail!("Filter column {} must be NOT NULL", filter);
+        }
+    }
+    let conflict = spec
+        .conflict
+        .clone()
+        .unwrap_or_else(|| table.primary_key.clone());
+    for column in &conflict {
+        field(column)?;
+    }
+
+    // Schema ----------------------------------------------------------------------------
+    let mut schema = String::from("diesel::table! {\n");
+    if !enums.is_empty() {
+        schema.push_str("    use diesel::sql_types::*;\n");
+        for (sql_enum, _) in &enums {
+            schema.push_str(&format!(
+                "    use super::sql_types::{};\n",
+                camel_case(&sql_enum.name)
+            ));
+        }
+        schema.push('\n');
+    }
+    schema.push_str(&format!(
+        "    {} ({}) {{\n",
+        spec.table,
+        table.primary_key.join(", ")
+    ));
+    for field in &fields {
+        let diesel_type = match field.nullable {
+            true => format!("Nullable<{}>", field.diesel_type),
+            false => field.diesel_type.clone(),
+        };
+        schema.push_str(&format!("        {} -> {},\n", field.name, diesel_type));
+    }
+    schema.push_str("    }\n}\n");
+
+    // Model -----------------------------------------------------------------------------
+    let mut enum_definitions = String::new();
+    for (sql_enum, name) in &enums {
+        if existing_enums.contains(name) {
+            imports.insert(format!("super::{}", name));
+            continue;
+        }
+        if sql_enum.values.is_empty() {
+            eyre::bail!("Enum {} has no value", sql_enum.name);
+        }
+        enum_definitions.push_str(&render_enum(sql_enum, name));
+        for import in [
+            "diesel::deserialize::FromSql",
+            "diesel::deserialize::FromSqlRow",
+            "diesel::expression::AsExpression",
+            "diesel::pg::PgValue",
+            "diesel::serialize::IsNull",
+            "diesel::serialize::Output",
+            "diesel::serialize::ToSql",
+            "std::io::Write",
+            "strum::Display",
+            "strum::EnumString",
+        ] {
+            imports.insert(import.to_string());
+        }
+    }
+
+    let struct_fields: String = fields
+        .iter()
+        .map(|field| match field.nullable {
+            true => format!("    pub {}: Option<{}>,\n", field.name, field.rust_type),
+            false => format!("    pub {}: {},\n", field.name, field.rust_type),
+        })
+        .collect();
+
+    let columns: Vec<String> = fields.iter().map(|field| field.name.clone()).collect();
+    let updates: Vec<String> = columns
+        .iter()
+        .filter(|column| !conflict.contains(column))
+        .cloned()
+        .collect();
+    let copy_fields: String = fields
+        .iter()
+        .map(|field| match field.nullable {
+            true => format!(
+                "            self.{}.as_ref().map(|value| {}),\n",
+                field.name,
+                copy_text(field, "value")
+            ),
+            false => format!(
+                "            Some({}),\n",
+                copy_text(field, &format!("self.{}", field.name))
+            ),
+        })
+        .collect();
+
+    let (filter_struct, filter_parse, filter_clauses) = match spec.filters.is_empty() {
+        true => (String::new(), String::new(), String::new()),
+        false => {
+            let mut filter_struct = format!(
+                "#[derive(Deserialize, Serialize, Debug)]\npub struct {}Filter {{\n",
+                model
+            );
+            let mut filter_clauses = String::new();
+            for filter in &spec.filters {
+                filter_struct.push_str(&format!(
+                    "    pub {}: {},\n",
+                    filter,
+                    field(filter)?.rust_type
+                ));
+                filter_clauses.push_str(&format!(
+                    "                .filter({name}.eq(query_filter.{name}))\n",
+                    name = filter
+                ));
+            }
+            filter_struct.push_str("}\n\n");
+            let filter_parse = format!(
+                "        let query_filter: {}Filter = serde_json::from_value(query.filters.clone())?;\n\n",
+                model
+            );
+            (filter_struct, filter_parse, filter_clauses)
+        }
+    };
+
+    let imports = imports
+        .iter()
+        .map(|import| format!("use {};\n", import))
+        .collect::<Vec<_>>()
+        .concat();
+
+    let model_file = format!(
+        "// Generated from {migration} by `cargo xtask new-table`
+use super::schemas;
+use crate::Range;
+use crate::RangeQuery;
+use crate::RowStream;
+use crate::SinkWriter;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_builder::QueryFragment;
+use serde::Deserialize;
+use serde::Serialize;
+{imports}
+{enum_definitions}// Database tables are defined here ------------------------------------------------------
+#[derive(Queryable, Selectable, Insertable, Debug, Clone)]
+#[diesel(table_name = schemas::{table})]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct {model} {{
+{struct_fields}}}
+
+impl SinkWriter for {model} {{
+    type Table = schemas::{table}::table;
+
+    const TABLE: &'static str = \"{table}\";
+    const COLUMNS: &'static [&'static str] = {columns};
+    const CONFLICT: &'static [&'static str] = {conflict};
+    const UPDATE: &'static [&'static str] = {updates};
+
+    fn insert(rows: &[Self]) -> impl QueryFragment<Pg> + '_ {{
+        diesel::insert_into(schemas::{table}::table).values(rows)
+    }}
+
+    fn copy_row(&self) -> Vec<Option<String>> {{
+        vec![
+{copy_fields}        ]
+    }}
+}}
+
+{filter_struct}// Implement RowStream for {model} -------------------------------------------------------
+impl RowStream for {model} {{
+    fn query(pool: &mut PgConnection, query: &RangeQuery) -> eyre::Result<Vec<Self>> {{
+        use schemas::{table}::dsl::*;
+{filter_parse}        if let Range::{range_kind} {{
+            from: range_from,
+            to: range_to,
+        }} = query.range
+        {{
+            let rows = {table}
+                .filter({range}.ge(range_from))
+                .filter({range}.le(range_to))
+{filter_clauses}                .order({range}.asc())
+                .load(pool)?;
+            Ok(rows)
+        }} else {{
+            Err(eyre::eyre!(\"Invalid range type\"))
+        }}
+    }}
+}}
+",
+        migration = spec.migration.display(),
+        table = spec.table,
+        columns = string_list(&columns),
+        conflict = string_list(&conflict),
+        updates = string_list(&updates),
+        range = spec.range,
+    );
+
+    Ok(Generated {
+        schema,
+        sql_types: enums
+            .iter()
+            .map(|(sql_enum, _)| (camel_case(&sql_enum.name), render_sql_type(sql_enum)))
+            .collect(),
+        model: model_file,
+        variant: camel_case(&spec.table),
+    })
+}
+
+/// Insert `text` after the line holding the last occurrence of `needle`
+fn insert_after_last_line(content: &mut String, needle: &str, text: &str) -> eyre::Result<()> {
+    let position = content
+        .rfind(needle)
+        .ok_or_else(|| eyre::eyre!("{} not found", needle))?;
+    let line_end = content[position..]
+        .find('\n')
+        .map(|offset| position + offset + 1)
+        .unwrap_or(content.len());
+    content.insert_str(line_end, text);
+    Ok(())
+}
+
+/// Create tier_N module, declare it in database/src/lib.rs, embed its migrations & add the database feature
+fn create_tier(root: &Path, tier: &str, variant: &str) -> eyre::Result<()> {
+    let number = tier.trim_start_matches("tier_");
+    let tier_dir = root.join("database/src").join(tier);
+    std::fs::create_dir_all(&tier_dir)?;
+    std::fs::write(tier_dir.join("schemas.rs"), "")?;
+    std::fs::write(
+        tier_dir.join("mod.rs"),
+        format!(
+            "mod schemas;
+
+use serde::Deserialize;
+use serde::Serialize;
+use strum::EnumIter;
+use strum::EnumString;
+
+#[derive(
+    EnumString, EnumIter, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, Hash,
+)]
+#[serde(rename_all = \"lowercase\")]
+pub enum Table {{
+    #[strum(ascii_case_insensitive)]
+    #[default]
+    {variant},
+}}
+"
+        ),
+    )?;
+
+    let lib_path = root.join("database/src/lib.rs");
+    let mut lib = std::fs::read_to_string(&lib_path)?;
+    insert_after_last_line(
+        &mut lib,
+        "pub mod tier_",
+        &format!("\n#[cfg(feature = \"{tier}\")]\npub mod {tier};\n"),
+    )?;
+    insert_after_last_line(
+        &mut lib,
+        "::Table),",
+        &format!("\n    #[cfg(feature = \"{tier}\")]\n    Tier{number}({tier}::Table),\n"),
+    )?;
+    insert_after_last_line(
+        &mut lib,
+        "tables.extend(tier_",
+        &format!(
+            "\n        #[cfg(feature = \"{tier}\")]\n        tables.extend({tier}::Table::iter().map(Table::Tier{number}));\n"
+        ),
+    )?;
+    std::fs::write(&lib_path, lib)?;
+
+    let migrations_path = root.join("database/src/migrations.rs");
+    let mut migrations = std::fs::read_to_string(&migrations_path)?;
+    insert_after_last_line(
+        &mut migrations,
+        "embed_migrations!(\"migrations/tier_",
+        &format!(
+            "\n#[cfg(feature = \"{tier}\")]\nconst TIER_{number}: EmbeddedMigrations = embed_migrations!(\"migrations/{tier}\");\n"
+        ),
+    )?;
+    insert_after_last_line(
+        &mut migrations,
+        "=> Some(TIER_",
+        &format!(
+            "\n        #[cfg(feature = \"{tier}\")]\n        \"{tier}\" => Some(TIER_{number}),\n"
+        ),
+    )?;
+    std::fs::write(&migrations_path, migrations)?;
+
+    let manifest_path = root.join("database/Cargo.toml");
+    let mut manifest = workspace::read_toml(&manifest_path)?;
+    let features = manifest["features"]
+        .as_table_mut()
+        .ok_or_else(|| eyre::eyre!("database/Cargo.toml has no [features]"))?;
+    let full = features
+        .remove("full")
+        .ok_or_else(|| eyre::eyre!("database/Cargo.toml has no full feature"))?;
+    features.insert(tier, toml_edit::value(Array::new()));
+    features.insert("full", full);
+    manifest["features"]["full"]
+        .as_array_mut()
+        .ok_or_else(|| eyre::eyre!("full feature is not an array"))?
+        .push(tier);
+    workspace::write_toml(&manifest_path, &manifest)?;
+
+    println!("Created database feature {}", tier);
+    Ok(())
+}
+
+fn write_schema(tier_dir: &Path, generated: &Generated) -> eyre::Result<()> {
+    let path = tier_dir.join("schemas.rs");
+    let mut content = std::fs::read_to_string(&path)?;
+
+    let missing: String = generated
+        .sql_types
+        .iter()
+        .filter(|(name, _)| !content.contains(&format!("pub struct {};", name)))
+        .map(|(_, declaration)| declaration.as_str())
+        .collect();
+    if !missing.is_empty() {
+        match content.find("pub mod sql_types {") {
+            Some(start) => {
+                let end = schema::block_end(&content, start).unwrap();
+                content.insert_str(end, &missing);
+            }
+            None => content.insert_str(0, &format!("pub mod sql_types {{\n{}}}\n\n", missing)),
+        }
+    }
+
+    if !content.is_empty() && !content.ends_with("\n\n") {
+        content.push('\n');
+    }
+    content.push_str(&generated.schema);
+    std::fs::write(&path, content)?;
+    Ok(())
+}
+
+fn register_table(tier_dir: &Path, table: &str, variant: &str, new_tier: bool) -> eyre::Result<()> {
+    let path = tier_dir.join("mod.rs");
+    let mut content = std::fs::read_to_string(&path)?;
+
+    insert_after_last_line(
+        &mut content,
+        "mod schemas;",
+        &format!("mod {table};\npub use {table}::*;\n"),
+    )?;
+
+    if !new_tier {
+        let start = content
+            .find("pub enum Table {")
+            .ok_or_else(|| eyre::eyre!("Table enum not found in {}", path.display()))?;
+        let end = schema::block_end(&content, start).unwrap();
+        content.insert_str(
+            end,
+            &format!("    #[strum(ascii_case_insensitive)]\n    {},\n", variant),
+        );
+    }
+
+    std::fs::write(&path, content)?;
+    Ok(())
+}
+
+/// Rust enums declared in the files of a tier
+fn tier_enums(tier_dir: &Path) -> eyre::Result<Vec<String>> {
+    let mut enums = vec![];
+    if !tier_dir.exists() {
+        return Ok(enums);
+    }
+    for entry in std::fs::read_dir(tier_dir)? {
+        let content = std::fs::read_to_string(entry?.path())?;
+        for line in content.lines() {
+            if let Some(name) = line.trim().strip_prefix("pub enum ") {
+                enums.push(name.trim_end_matches('{').trim().to_string());
+            }
+        }
+    }
+    Ok(enums)
+}
+
+pub fn run(root: &Path, spec_path: &Path) -> eyre::Result<()> {
+    let spec: TableSpec = toml_edit::de::from_str(&std::fs::read_to_string(spec_path)?)?;
+
+    if spec.tier.strip_prefix("tier_").map_or(true, |n| {
+        n.is_empty() || !n.chars().all(|c| c.is_ascii_digit())
+    }) {
+        eyre::bail!("Invalid tier: {}, expected tier_<number>", spec.tier);
+    }
+
+    let tier_migrations = Path::new(check_schema::MIGRATIONS).join(&spec.tier);
+    if spec.migration.parent() != Some(tier_migrations.as_path()) {
+        eyre::bail!(
+            "{} must be a migration of {}, embedded with the {} feature",
+            spec.migration.display(),
+            tier_migrations.display(),
+            spec.tier
+        );
+    }
+
+    let migration = root.join(&spec.migration).join("up.sql");
+    if !sql::created_tables(&std::fs::read_to_string(&migration)?).contains(&spec.table) {
+        eyre::bail!(
+            "{} does not create table {}",
+            migration.display(),
+            spec.table
+        );
+    }
+
+    if let Some(existing) = schema::load_schemas(root)?
+        .into_iter()
+        .find(|table| table.name == spec.table)
+    {
+        eyre::bail!(
+            "Table {} is already declared in {}",
+            spec.table,
+            existing.file.display()
+        );
+    }
+
+    let tier_dir = root.join("database/src").join(&spec.tier);
+    let module = tier_dir.join(format!("{}.rs", spec.table));
+    if module.exists() {
+        eyre::bail!("{} already exists", module.display());
+    }
+
+    let migrations = SqlSchema::from_migrations(&root.join(check_schema::MIGRATIONS))?;
+    let generated = generate(&spec, &migrations, &tier_enums(&tier_dir)?)?;
+
+    let new_tier = !tier_dir.exists();
+    if new_tier {
+        create_tier(root, &spec.tier, &generated.variant)?;
+    }
+    write_schema(&tier_dir, &generated)?;
+    std::fs::write(&module, &generated.model)?;
+    register_table(&tier_dir, &spec.table, &generated.variant, new_tier)?;
+    println!("Created {}", module.display());
+
+    workspace::format(root, "database");
+    check_schema::run(root)?;
+
+    println!(
+        "Table {} available as Table::Tier{}({}::Table::{})",
+        spec.table,
+        spec.tier.trim_start_matches("tier_"),
+        spec.tier,
+        generated.variant
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIGRATION: &str = "
+        CREATE TYPE transfer_kinds AS ENUM ('IN', 'OUT');
+        CREATE TABLE transfers
+        (
+            wallet       VARCHAR(80) NOT NULL,
+            kind         transfer_kinds NOT NULL,
+            amount       NUMERIC     NOT NULL,
+            chain_id     BIGINT      NOT NULL,
+            block_number BIGINT      NOT NULL,
+            note         TEXT,
+            PRIMARY KEY (wallet, block_number)
+        );
+    ";
+
+    fn spec() -> TableSpec {
+        toml_edit::de::from_str(
+            r#"
+            migration = "database/migrations/tier_4/2024-09-01-000000_create_transfers"
+            table = "transfers"
+            tier = "tier_4"
+            model = "Transfer"
+            range = "block_number"
+            filters = ["chain_id"]
+            enums = { transfer_kinds = "TransferKind" }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut migrations = SqlSchema::default();
+        migrations.apply(MIGRATION).unwrap();
+
+        let generated = generate(&spec(), &migrations, &[]).unwrap();
+        assert_eq!(generated.variant, "Transfers");
+        assert_eq!(generated.sql_types[0].0, "TransferKinds");
+        assert!(generated
+            .schema
+            .contains("    transfers (wallet, block_number) {\n"));
+        assert!(generated
+            .schema
+            .contains("        kind -> TransferKinds,\n"));
+        assert!(generated
+            .schema
+            .contains("        note -> Nullable<VarChar>,\n"));
+        assert!(generated.model.contains("pub enum TransferKind {"));
+        assert!(generated.model.contains("    pub note: Option<String>,\n"));
+        assert!(generated.model.contains(
+            "const CONFLICT: &'static [&'static str] = &[\"wallet\", \"block_number\"];"
+        ));
+        assert!(generated
+            .model
+            .contains("            self.note.as_ref().map(|value| value.clone()),\n"));
+        assert!(generated
+            .model
+            .contains("pub struct TransferFilter {\n    pub chain_id: i64,\n"));
+        assert!(generated.model.contains("if let Range::Numeric {"));
+
+        // Enum already declared in the tier
+        let generated = generate(&spec(), &migrations, &["TransferKind".to_string()]).unwrap();
+        assert!(!generated.model.contains("pub enum TransferKind"));
+        assert!(generated.model.contains("use super::TransferKind;"));
+
+        let mut invalid = spec();
+        invalid.range = "note".to_string();
+        assert!(generate(&invalid, &migrations, &[]).is_err());
+        invalid.range = "wallet".to_string();
+        assert!(generate(&invalid, &migrations, &[]).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Column of a `diesel::table!` declaration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaColumn {
+    pub name: String,
+    /// Diesel type without `Nullable`, eg: VarChar
+    pub diesel_type: String,
+    pub nullable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaTable {
+    pub name: String,
+    pub primary_key: Vec<String>,
+    pub columns: Vec<SchemaColumn>,
+    /// schemas.rs file declaring the table
+    pub file: PathBuf,
+}
+
+/// Index of the `}` closing the block opened by the first `{` at or after `start`
+pub fn block_end(content: &str, start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in content[start..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(start + i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Parse the `diesel::table!` declarations of a schemas.rs file
+pub fn parse_schema(content: &str, file: &Path) -> eyre::Result<Vec<SchemaTable>> {
+    let mut tables = vec![];
+    let mut offset = 0;
+
+    while let Some(position) = content[offset..].find("diesel::table!") {
+        let start = offset + position;
+        let end = block_end(content, start)
+            .ok_or_else(|| eyre::eyre!("{}: unclosed diesel::table!", file.display()))?;
+        let body = &content[content[start..].find('{').unwrap() + start + 1..end];
+
+        let mut table: Option<SchemaTable> = None;
+        for line in body.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with("use ") || line.starts_with("#[") {
+                continue;
+            }
+
+            if let Some(table) = table.as_mut() {
+                let Some((name, diesel_type)) = line.trim_end_matches(',').split_once("->") else {
+                    continue;
+                };
+                let diesel_type = diesel_type.trim();
+                let (diesel_type, nullable) = match diesel_type
+                    .strip_prefix("Nullable<")
+                    .and_then(|inner| inner.strip_suffix('>'))
+                {
+                    Some(inner) => (inner, true),
+                    None => (diesel_type, false),
+                };
+                table.columns.push(SchemaColumn {
+                    name: name.trim().to_string(),
+                    diesel_type: diesel_type.to_string(),
+                    nullable,
+                });
+            } else if let Some((name, primary_key)) = line.trim_end_matches('{').split_once('(') {
+                table = Some(SchemaTable {
+                    name: name.trim().to_string(),
+                    primary_key: primary_key
+                        .trim()
+                        .trim_end_matches(')')
+                        .split(',')
+                        .map(|column| column.trim().to_string())
+                        .filter(|column| !column.is_empty())
+                        .collect(),
+                    columns: vec![],
+                    file: file.to_path_buf(),
+                });
+            }
+        }
+
+        tables.extend(table);
+        offset = end;
+    }
+
+    Ok(tables)
+}
+
+/// All tables declared in database/src/**/schemas.rs
+pub fn load_schemas(root: &Path) -> eyre::Result<Vec<SchemaTable>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(root.join("database/src"))? {
+        let file = entry?.path().join("schemas.rs");
+        if file.exists() {
+            files.push(file);
+        }
+    }
+    files.sort();
+
+    let mut tables = vec![];
+    for file in files {
+        let content = std::fs::read_to_string(&file)?;
+        let relative = file.strip_prefix(root).unwrap_or(&file);
+        tables.extend(parse_schema(&content, relative)?);
+    }
+    Ok(tables)
+}
+
+/// Diesel types that are interchangeable for Postgres, eg: BigSerial & BigInt
+pub fn normalize_type(diesel_type: &str) -> &str {
+    match diesel_type {
+        "VarChar" | "Varchar" | "Text" | "Bpchar" => "Text",
+        "BigInt" | "BigSerial" | "Int8" => "BigInt",
+        "Integer" | "Serial" | "Int4" => "Integer",
+        "SmallInt" | "SmallSerial" | "Int2" => "SmallInt",
+        "Float" | "Float4" => "Float",
+        "Double" | "Float8" => "Double",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_schema() {
+        let content = "
+pub mod sql_types {
+    pub struct ActionTypes;
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ActionTypes;
+
+    actions (id) {
+        id -> VarChar,
+        action_type -> ActionTypes,
+        created_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    buy_sell (user, timestamp) {
+        user -> VarChar,
+        timestamp -> Timestamp
+    }
+}";
+        let tables = parse_schema(content, Path::new("schemas.rs")).unwrap();
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].name, "actions");
+        assert_eq!(tables[0].primary_key, vec!["id"]);
+        assert_eq!(
+            tables[0].columns[2],
+            SchemaColumn {
+                name: "created_at".to_string(),
+                diesel_type: "Timestamptz".to_string(),
+                nullable: true,
+            }
+        );
+        assert_eq!(tables[1].primary_key, vec!["user", "timestamp"]);
+        assert_eq!(tables[1].columns.len(), 2);
+    }
+}
@@ -0,0 +1,631 @@
+use std::path::Path;
+
+/// Column as declared by the migrations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlColumn {
+    pub name: String,
+    /// Lowercased type without parameters, eg: varchar, timestamp with time zone
+    pub sql_type: String,
+    pub nullable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlTable {
+    pub name: String,
+    pub columns: Vec<SqlColumn>,
+    pub primary_key: Vec<String>,
+}
+
+impl SqlTable {
+    pub fn column(&self, name: &str) -> Option<&SqlColumn> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+}
+
+/// Postgres enum created with `CREATE TYPE ... AS ENUM`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlEnum {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+/// Tables & enum types resulting from applying migrations in order
+#[derive(Debug, Default)]
+pub struct SqlSchema {
+    pub tables: Vec<SqlTable>,
+    pub enums: Vec<SqlEnum>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    /// Double-quoted identifier, case is preserved
+    Ident(String),
+    Quoted(String),
+    Symbol(char),
+}
+
+fn tokenize(sql: &str) -> Vec<Token> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+        } else if c == '\'' || c == '"' {
+            let mut value = String::new();
+            i += 1;
+            while i < chars.len() {
+                if chars[i] == c && chars.get(i + 1) == Some(&c) {
+                    value.push(c);
+                    i += 2;
+                } else if chars[i] == c {
+                    i += 1;
+                    break;
+                } else {
+                    value.push(chars[i]);
+                    i += 1;
+                }
+            }
+            tokens.push(match c {
+                '\'' => Token::Quoted(value),
+                _ => Token::Ident(value),
+            });
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect()));
+        } else {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        }
+    }
+
+    tokens
+}
+
+fn is_kw(token: Option<&Token>, keyword: &str) -> bool {
+    matches!(token, Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+}
+
+/// Split tokens on `separator` outside of parentheses
+fn split_top_level(tokens: &[Token], separator: char) -> Vec<Vec<Token>> {
+    let mut parts = vec![vec![]];
+    let mut depth = 0;
+    for token in tokens {
+        match token {
+            Token::Symbol('(') => depth += 1,
+            Token::Symbol(')') => depth -= 1,
+            Token::Symbol(c) if *c == separator && depth == 0 => {
+                parts.push(vec![]);
+                continue;
+            }
+            _ => (),
+        }
+        parts.last_mut().unwrap().push(token.clone());
+    }
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+const COLUMN_CONSTRAINTS: [&str; 10] = [
+    "not",
+    "null",
+    "primary",
+    "unique",
+    "check",
+    "references",
+    "default",
+    "constraint",
+    "generated",
+    "collate",
+];
+
+/// Parse a type, skipping its parameters, eg: VARCHAR(80) -> varchar
+fn parse_type(tokens: &[Token], stop: &[&str]) -> (String, usize) {
+    let mut words = vec![];
+    let mut depth = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Symbol('(') => depth += 1,
+            Token::Symbol(')') => depth -= 1,
+            Token::Symbol('[') if depth == 0 => words.push("[]".to_string()),
+            Token::Symbol(']') if depth == 0 => (),
+            Token::Word(word) if depth == 0 => {
+                if stop.iter().any(|kw| word.eq_ignore_ascii_case(kw)) {
+                    break;
+                }
+                words.push(word.to_lowercase());
+            }
+            Token::Ident(ident) if depth == 0 => words.push(ident.clone()),
+            _ => (),
+        }
+        i += 1;
+    }
+    (words.join(" ").replace(" []", "[]"), i)
+}
+
+fn is_serial(sql_type: &str) -> bool {
+    matches!(
+        sql_type,
+        "serial" | "serial4" | "bigserial" | "serial8" | "smallserial" | "serial2"
+    )
+}
+
+/// Parse a column definition, return the column and whether it is the primary key
+fn parse_column(tokens: &[Token]) -> eyre::Result<(SqlColumn, bool)> {
+    let name = match tokens.first() {
+        Some(Token::Word(word)) => word.to_lowercase(),
+        Some(Token::Ident(ident)) => ident.clone(),
+        token => eyre::bail!("Expected column name, found {:?}", token),
+    };
+    let (sql_type, end) = parse_type(&tokens[1..], &COLUMN_CONSTRAINTS);
+    if sql_type.is_empty() {
+        eyre::bail!("Column {} has no type", name);
+    }
+
+    let constraints = &tokens[1 + end..];
+    let mut not_null = is_serial(&sql_type);
+    let mut primary_key = false;
+    for (i, token) in constraints.iter().enumerate() {
+        if is_kw(Some(token), "not") && is_kw(constraints.get(i + 1), "null") {
+            not_null = true;
+        }
+        if is_kw(Some(token), "primary") && is_kw(constraints.get(i + 1), "key") {
+            primary_key = true;
+        }
+    }
+
+    let column = SqlColumn {
+        name,
+        sql_type,
+        nullable: !(not_null || primary_key),
+    };
+    Ok((column, primary_key))
+}
+
+fn parse_column_list(tokens: &[Token]) -> Vec<String> {
+    tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Word(word) => Some(word.to_lowercase()),
+            Token::Ident(ident) => Some(ident.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Tokens of a parenthesized group starting at `start`, and the index after it
+fn group(tokens: &[Token], start: usize) -> eyre::Result<(&[Token], usize)> {
+    if tokens.get(start) != Some(&Token::Symbol('(')) {
+        eyre::bail!("Expected '(', found {:?}", tokens.get(start));
+    }
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::Symbol('(') => depth += 1,
+            Token::Symbol(')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((&tokens[start + 1..i], i + 1));
+                }
+            }
+            _ => (),
+        }
+    }
+    eyre::bail!("Unbalanced parentheses")
+}
+
+impl SqlSchema {
+    /// Apply the `up.sql` of every migration under `dir`, in version order across the sets
+    pub fn from_migrations(dir: &Path) -> eyre::Result<Self> {
+        let mut migrations = vec![];
+        for set in std::fs::read_dir(dir)? {
+            let set = set?.path();
+            if !set.is_dir() {
+                continue;
+            }
+            for migration in std::fs::read_dir(&set)? {
+                let migration = migration?.path();
+                if migration.join("up.sql").exists() {
+                    migrations.push(migration);
+                }
+            }
+        }
+        migrations.sort_by_key(|migration| migration.file_name().map(|name| name.to_owned()));
+
+        let mut schema = Self::default();
+        for migration in migrations {
+            let sql = std::fs::read_to_string(migration.join("up.sql"))?;
+            schema
+                .apply(&sql)
+                .map_err(|error| eyre::eyre!("{}: {}", migration.display(), error))?;
+        }
+        Ok(schema)
+    }
+
+    pub fn table(&self, name: &str) -> Option<&SqlTable> {
+        self.tables.iter().find(|table| table.name == name)
+    }
+
+    pub fn enum_type(&self, name: &str) -> Option<&SqlEnum> {
+        self.enums.iter().find(|sql_enum| sql_enum.name == name)
+    }
+
+    fn table_mut(&mut self, name: &str) -> eyre::Result<&mut SqlTable> {
+        self.tables
+            .iter_mut()
+            .find(|table| table.name == name)
+            .ok_or_else(|| eyre::eyre!("ALTER TABLE on unknown table: {}", name))
+    }
+
+    /// Apply the statements of a migration: CREATE/ALTER/DROP TABLE & CREATE/DROP TYPE
+    pub fn apply(&mut self, sql: &str) -> eyre::Result<()> {
+        let tokens = tokenize(sql);
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let kw = |offset: usize, keyword: &str| is_kw(tokens.get(i + offset), keyword);
+
+            if kw(0, "create") && kw(1, "type") {
+                i = self.create_type(&tokens, i + 2)?;
+            } else if kw(0, "create") && kw(1, "table") {
+                i = self.create_table(&tokens, i + 2)?;
+            } else if kw(0, "alter") && kw(1, "table") {
+                i = self.alter_table(&tokens, i + 2)?;
+            } else if kw(0, "drop") && (kw(1, "table") || kw(1, "type")) {
+                let is_table = kw(1, "table");
+                let end = statement_end(&tokens, i);
+                let mut names = parse_column_list(&tokens[i + 2..end]);
+                names.retain(|name| !matches!(name.as_str(), "if" | "exists" | "cascade"));
+                if is_table {
+                    self.tables.retain(|table| !names.contains(&table.name));
+                } else {
+                    self.enums
+                        .retain(|sql_enum| !names.contains(&sql_enum.name));
+                }
+                i = end;
+            } else {
+                i += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn create_type(&mut self, tokens: &[Token], start: usize) -> eyre::Result<usize> {
+        let name = parse_column_list(&tokens[start..start + 1]).concat();
+        if !(is_kw(tokens.get(start + 1), "as") && is_kw(tokens.get(start + 2), "enum")) {
+            // Only enum types are tracked
+            return Ok(start + 1);
+        }
+
+        let (values, end) = group(tokens, start + 3)?;
+        let values = values
+            .iter()
+            .filter_map(|token| match token {
+                Token::Quoted(value) => Some(value.clone()),
+                _ => None,
+            })
+            .collect();
+        self.enums.retain(|sql_enum| sql_enum.name != name);
+        self.enums.push(SqlEnum { name, values });
+        Ok(end)
+    }
+
+    fn create_table(&mut self, tokens: &[Token], mut start: usize) -> eyre::Result<usize> {
+        if is_kw(tokens.get(start), "if") {
+            start += 3;
+        }
+        let name = parse_column_list(&tokens[start..start + 1]).concat();
+        // Schema qualified name, eg: public.actions
+        let (name, start) = if tokens.get(start + 1) == Some(&Token::Symbol('.')) {
+            (
+                parse_column_list(&tokens[start + 2..start + 3]).concat(),
+                start + 2,
+            )
+        } else {
+            (name, start)
+        };
+
+        let (body, end) = group(tokens, start + 1)?;
+        let mut table = SqlTable {
+            name: name.clone(),
+            columns: vec![],
+            primary_key: vec![],
+        };
+
+        for mut item in split_top_level(body, ',') {
+            if is_kw(item.first(), "constraint") {
+                item.drain(..2);
+            }
+            if is_kw(item.first(), "primary") {
+                let (columns, _) = group(&item, 2)?;
+                table.primary_key = parse_column_list(columns);
+            } else if ["unique", "check", "foreign", "exclude"]
+                .iter()
+                .any(|kw| is_kw(item.first(), kw))
+            {
+                continue;
+            } else {
+                let (column, primary_key) = parse_column(&item)
+                    .map_err(|error| eyre::eyre!("Table {}: {}", name, error))?;
+                if primary_key {
+                    table.primary_key = vec![column.name.clone()];
+                }
+                table.columns.push(column);
+            }
+        }
+
+        for column in table.columns.iter_mut() {
+            if table.primary_key.contains(&column.name) {
+                column.nullable = false;
+            }
+        }
+
+        // CREATE TABLE IF NOT EXISTS keeps the existing table
+        if self.table(&name).is_none() {
+            self.tables.push(table);
+        }
+        Ok(end)
+    }
+
+    fn alter_table(&mut self, tokens: &[Token], mut start: usize) -> eyre::Result<usize> {
+        if is_kw(tokens.get(start), "if") {
+            start += 2;
+        }
+        if is_kw(tokens.get(start), "only") {
+            start += 1;
+        }
+        let name = parse_column_list(&tokens[start..start + 1]).concat();
+        let end = statement_end(tokens, start);
+
+        for mut action in split_top_level(&tokens[start + 1..end], ',') {
+            let table = self.table_mut(&name)?;
+            let verb = action.remove(0);
+            let has_column_kw = is_kw(action.first(), "column");
+            if has_column_kw {
+                action.remove(0);
+            }
+
+            if is_kw(Some(&verb), "add") {
+                if ["constraint", "primary", "unique", "check", "foreign"]
+                    .iter()
+                    .any(|kw| is_kw(action.first(), kw))
+                {
+                    if is_kw(action.first(), "constraint") {
+                        action.drain(..2);
+                    }
+                    if is_kw(action.first(), "primary") {
+                        let (columns, _) = group(&action, 2)?;
+                        table.primary_key = parse_column_list(columns);
+                    }
+                    continue;
+                }
+                if is_kw(action.first(), "if") {
+                    action.drain(..3);
+                }
+                let (column, primary_key) = parse_column(&action)?;
+                if primary_key {
+                    table.primary_key = vec![column.name.clone()];
+                }
+                if table.column(&column.name).is_none() {
+                    table.columns.push(column);
+                }
+            } else if is_kw(Some(&verb), "drop") && !is_kw(action.first(), "constraint") {
+                if is_kw(action.first(), "if") {
+                    action.drain(..2);
+                }
+                let column = parse_column_list(&action[..1]).concat();
+                table.columns.retain(|c| c.name != column);
+                table.primary_key.retain(|c| *c != column);
+            } else if is_kw(Some(&verb), "alter") {
+                let column_name = parse_column_list(&action[..1]).concat();
+                let column = table
+                    .columns
+                    .iter_mut()
+                    .find(|column| column.name == column_name)
+                    .ok_or_else(|| {
+                        eyre::eyre!("ALTER COLUMN on unknown column: {}.{}", name, column_name)
+                    })?;
+                let rest = &action[1..];
+                let kw = |offset: usize, keyword: &str| is_kw(rest.get(offset), keyword);
+
+                if kw(0, "type") || (kw(0, "set") && kw(1, "data") && kw(2, "type")) {
+                    let skip = if kw(0, "type") { 1 } else { 3 };
+                    let (sql_type, _) = parse_type(&rest[skip..], &["using", "collate"]);
+                    column.sql_type = sql_type;
+                } else if kw(0, "set") && kw(1, "not") && kw(2, "null") {
+                    column.nullable = false;
+                } else if kw(0, "drop") && kw(1, "not") && kw(2, "null") {
+                    column.nullable = true;
+                }
+            } else if is_kw(Some(&verb), "rename") {
+                if is_kw(action.first(), "to") {
+                    let new_name = parse_column_list(&action[1..2]).concat();
+                    table.name = new_name;
+                } else if has_column_kw || is_kw(action.get(1), "to") {
+                    let names = parse_column_list(&action);
+                    if let [old, _, new] = names.as_slice() {
+                        for column in table.columns.iter_mut().filter(|c| c.name == *old) {
+                            column.name = new.clone();
+                        }
+                        for column in table.primary_key.iter_mut().filter(|c| *c == old) {
+                            *column = new.clone();
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(end)
+    }
+}
+
+/// Names of the tables created by a migration
+pub fn created_tables(sql: &str) -> Vec<String> {
+    let tokens = tokenize(sql);
+    let mut tables = vec![];
+    for i in 0..tokens.len() {
+        if is_kw(tokens.get(i), "create") && is_kw(tokens.get(i + 1), "table") {
+            let start = if is_kw(tokens.get(i + 2), "if") {
+                i + 5
+            } else {
+                i + 2
+            };
+            tables.extend(parse_column_list(
+                &tokens[start.min(tokens.len())..(start + 1).min(tokens.len())],
+            ));
+        }
+    }
+    tables
+}
+
+/// Index of the `;` ending the statement starting at `start`, or the end of the tokens
+fn statement_end(tokens: &[Token], start: usize) -> usize {
+    tokens[start..]
+        .iter()
+        .position(|token| *token == Token::Symbol(';'))
+        .map(|offset| start + offset)
+        .unwrap_or(tokens.len())
+}
+
+/// Diesel SQL type of a Postgres type, eg: bigint -> BigInt
+pub fn diesel_type(sql_type: &str, schema: &SqlSchema) -> Option<String> {
+    let diesel_type = match sql_type {
+        "varchar" | "character varying" | "text" | "char" | "character" | "bpchar" => "VarChar",
+        "bigint" | "int8" | "bigserial" | "serial8" => "BigInt",
+        "integer" | "int" | "int4" | "serial" | "serial4" => "Integer",
+        "smallint" | "int2" | "smallserial" | "serial2" => "SmallInt",
+        "numeric" | "decimal" => "Numeric",
+        "real" | "float4" => "Float",
+        "double precision" | "float8" => "Double",
+        "boolean" | "bool" => "Bool",
+        "jsonb" => "Jsonb",
+        "json" => "Json",
+        "bytea" => "Bytea",
+        "timestamp" | "timestamp without time zone" => "Timestamp",
+        "timestamptz" | "timestamp with time zone" => "Timestamptz",
+        "date" => "Date",
+        enum_type => {
+            return schema
+                .enum_type(enum_type)
+                .map(|sql_enum| camel_case(&sql_enum.name))
+        }
+    };
+    Some(diesel_type.to_string())
+}
+
+/// eg: balance_per_date -> BalancePerDate, IN-PROGRESS -> InProgress
+pub fn camel_case(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let lower = part.to_lowercase();
+            let mut chars = lower.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_migrations() {
+        let mut schema = SqlSchema::default();
+        schema
+            .apply(
+                "
+                DO $$
+                BEGIN
+                    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'action_types') THEN
+                        CREATE TYPE action_types AS ENUM ('SEND', 'RECEIVE');
+                    END IF;
+                END$$;
+
+                -- comment (with, parens)
+                CREATE TABLE IF NOT EXISTS transfers
+                (
+                    wallet      VARCHAR(80)  NOT NULL,
+                    kind        action_types NOT NULL,
+                    amount      NUMERIC(20, 8) CHECK (amount >= 0),
+                    moved_at    TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY (wallet, moved_at)
+                );
+
+                ALTER TABLE transfers ADD COLUMN IF NOT EXISTS chain_id BIGINT NOT NULL DEFAULT 1,
+                    ALTER COLUMN amount TYPE BIGINT USING amount::BIGINT,
+                    ALTER COLUMN amount SET NOT NULL;
+                ",
+            )
+            .unwrap();
+
+        assert_eq!(
+            schema.enum_type("action_types").unwrap().values,
+            vec!["SEND", "RECEIVE"]
+        );
+
+        let table = schema.table("transfers").unwrap();
+        assert_eq!(table.primary_key, vec!["wallet", "moved_at"]);
+        assert_eq!(
+            table.columns,
+            vec![
+                SqlColumn {
+                    name: "wallet".to_string(),
+                    sql_type: "varchar".to_string(),
+                    nullable: false,
+                },
+                SqlColumn {
+                    name: "kind".to_string(),
+                    sql_type: "action_types".to_string(),
+                    nullable: false,
+                },
+                SqlColumn {
+                    name: "amount".to_string(),
+                    sql_type: "bigint".to_string(),
+                    nullable: false,
+                },
+                SqlColumn {
+                    name: "moved_at".to_string(),
+                    sql_type: "timestamp with time zone".to_string(),
+                    nullable: false,
+                },
+                SqlColumn {
+                    name: "chain_id".to_string(),
+                    sql_type: "bigint".to_string(),
+                    nullable: false,
+                },
+            ]
+        );
+
+        assert_eq!(
+            diesel_type("action_types", &schema).as_deref(),
+            Some("ActionTypes")
+        );
+        assert_eq!(
+            diesel_type("timestamp with time zone", &schema).as_deref(),
+            Some("Timestamptz")
+        );
+        assert_eq!(diesel_type("uuid", &schema), None);
+    }
+}
@@ -0,0 +1,99 @@
+use database::Table;
+use std::path::Path;
+use std::path::PathBuf;
+use toml_edit::DocumentMut;
+
+/// Root of the cargo workspace
+pub fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask lives in the workspace root")
+        .to_path_buf()
+}
+
+pub fn read_toml(path: &Path) -> eyre::Result<DocumentMut> {
+    let content = std::fs::read_to_string(path)?;
+    let document = content.parse::<DocumentMut>()?;
+    Ok(document)
+}
+
+pub fn write_toml(path: &Path, document: &DocumentMut) -> eyre::Result<()> {
+    std::fs::write(path, document.to_string())?;
+    Ok(())
+}
+
+/// Format a package of the workspace, best effort since rendered code can exceed the line width
+pub fn format(root: &Path, package: &str) {
+    let formatted = std::process::Command::new("cargo")
+        .args(["fmt", "-p", package])
+        .current_dir(root)
+        .status();
+    if !formatted.is_ok_and(|status| status.success()) {
+        println!("Could not format {}, run `cargo fmt` manually", package);
+    }
+}
+
+/// Tier features declared by the database crate, eg: tier_1
+pub fn database_features(root: &Path) -> eyre::Result<Vec<String>> {
+    let manifest = read_toml(&root.join("database/Cargo.toml"))?;
+    let features = manifest["features"]
+        .as_table()
+        .ok_or_else(|| eyre::eyre!("database/Cargo.toml has no [features]"))?
+        .iter()
+        .map(|(feature, _)| feature.to_string())
+        .filter(|feature| feature != "full")
+        .collect();
+    Ok(features)
+}
+
+/// Database feature a table belongs to, eg: Tier1(Actions) -> tier_1
+pub fn table_feature(table: &Table) -> String {
+    let (tier, _) = split_table(table);
+    format!("tier_{}", tier.trim_start_matches("Tier"))
+}
+
+/// Rust expression of a table, eg: Table::Tier1(tier_1::Table::Actions)
+pub fn table_expr(table: &Table) -> String {
+    let (tier, variant) = split_table(table);
+    format!(
+        "Table::{}({}::Table::{})",
+        tier,
+        table_feature(table),
+        variant
+    )
+}
+
+fn split_table(table: &Table) -> (String, String) {
+    let debug = format!("{:?}", table);
+    let (tier, variant) = debug
+        .trim_end_matches(')')
+        .split_once('(')
+        .expect("Table debug format is Tier(Variant)");
+    (tier.to_string(), variant.to_string())
+}
+
+/// Find a table by the name used in messages, eg: actions
+pub fn find_table(name: &str) -> eyre::Result<Table> {
+    Table::all()
+        .into_iter()
+        .find(|table| table.name() == name)
+        .ok_or_else(|| {
+            let names: Vec<String> = Table::all().iter().map(Table::name).collect();
+            eyre::eyre!("Unknown table: {}, available tables: {:?}", name, names)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::tier_2;
+
+    #[test]
+    fn test_table_expr() {
+        let table = find_table("buysell").unwrap();
+        assert_eq!(table, Table::Tier2(tier_2::Table::BuySell));
+        assert_eq!(table_feature(&table), "tier_2");
+        assert_eq!(table_expr(&table), "Table::Tier2(tier_2::Table::BuySell)");
+        assert!(find_table("unknown").is_err());
+    }
+}